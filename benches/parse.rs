@@ -0,0 +1,102 @@
+//! Compares parsing whole files with `Elf::parse` against mapping them and parsing only what
+//! `process_one` needs. Run with `cargo bench --bench parse [DIR]...`, /usr/lib by default; the
+//! first run also measures reading the files from disk, so it is not counted.
+
+#[path = "../src/parse.rs"]
+mod parse;
+
+use goblin::elf::Elf;
+use memmap2::Mmap;
+use std::fs::File;
+use std::hint::black_box;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use walkdir::WalkDir;
+
+const RUNS: usize = 5;
+
+/// Regular files starting with the ELF magic
+fn elf_files(dirs: &[PathBuf]) -> Vec<PathBuf> {
+    dirs.iter()
+        .flat_map(|dir| WalkDir::new(dir).sort_by_file_name())
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| {
+            let mut magic = [0; 4];
+            File::open(entry.path())
+                .and_then(|mut file| file.read_exact(&mut magic))
+                .is_ok_and(|()| magic == *b"\x7fELF")
+        })
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+fn read_and_parse(path: &Path) -> usize {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(_) => return 0,
+    };
+    Elf::parse(&bytes).map_or(0, |elf| elf.libraries.len())
+}
+
+fn map_and_parse_dynamic(path: &Path) -> usize {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(_) => return 0,
+    };
+    let bytes = match unsafe { Mmap::map(&file) } {
+        Ok(bytes) => bytes,
+        Err(_) => return 0,
+    };
+    match parse::parse_dynamic(&bytes) {
+        Ok((_, _, Some(dynamic))) => dynamic.dyns.len(),
+        _ => 0,
+    }
+}
+
+/// Fastest of `RUNS` passes over all files, after one pass to warm the page cache
+fn measure(files: &[PathBuf], parse: fn(&Path) -> usize) -> Duration {
+    let pass = || {
+        let start = Instant::now();
+        for file in files {
+            black_box(parse(black_box(file)));
+        }
+        start.elapsed()
+    };
+    pass();
+    (0..RUNS).map(|_| pass()).min().unwrap()
+}
+
+fn main() {
+    let mut dirs = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    if dirs.is_empty() {
+        dirs.push(PathBuf::from("/usr/lib"));
+    }
+    let files = elf_files(&dirs);
+    let size = files
+        .iter()
+        .filter_map(|file| file.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum::<u64>();
+    println!("{} ELF files, {} MB", files.len(), size / 1_000_000);
+
+    let read = measure(&files, read_and_parse);
+    let mapped = measure(&files, map_and_parse_dynamic);
+    println!(
+        "read + Elf::parse:          {:>8.1} ms",
+        read.as_secs_f64() * 1000.0
+    );
+    println!(
+        "mmap + parse_dynamic:       {:>8.1} ms",
+        mapped.as_secs_f64() * 1000.0
+    );
+    println!(
+        "speedup:                    {:>8.1}x",
+        read.as_secs_f64() / mapped.as_secs_f64()
+    );
+}
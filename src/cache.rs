@@ -0,0 +1,141 @@
+use crate::{ElfInfo, ErrorKind};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{BufReader, BufWriter, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+/// Bumped whenever what is stored for a file changes, making older caches be ignored
+const VERSION: u32 = 3;
+
+/// What parsing a file gave, for the results worth remembering. Other errors are cheap to
+/// reproduce or may well go away on the next run.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Parsed {
+    Dynamic(ElfInfo),
+    Static { machine: u16 },
+}
+
+impl Parsed {
+    pub fn new(res: &Result<ElfInfo, ErrorKind>) -> Option<Self> {
+        match res {
+            Ok(info) => Some(Parsed::Dynamic(info.clone())),
+            Err(ErrorKind::StaticExecutable { machine }) => {
+                Some(Parsed::Static { machine: *machine })
+            }
+            Err(_) => None,
+        }
+    }
+
+    pub fn into_result(self) -> Result<ElfInfo, ErrorKind> {
+        match self {
+            Parsed::Dynamic(info) => Ok(info),
+            Parsed::Static { machine } => Err(ErrorKind::StaticExecutable { machine }),
+        }
+    }
+}
+
+/// Identifies a file, and a version of its contents as long as nobody resets its mtime
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    dev: u64,
+    ino: u64,
+    size: u64,
+    mtime: i64,
+    mtime_nsec: i64,
+}
+
+impl Stamp {
+    pub fn inode(&self) -> (u64, u64) {
+        (self.dev, self.ino)
+    }
+
+    pub fn new(metadata: &Metadata) -> Self {
+        Self {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+            size: metadata.size(),
+            mtime: metadata.mtime(),
+            mtime_nsec: metadata.mtime_nsec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    stamp: Stamp,
+    parsed: Parsed,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: Vec<Entry>,
+}
+
+/// Parse results of earlier runs, keyed by device and inode so that hard links and renames do not
+/// cost a parse. Entries of files that are gone are only dropped by rebuilding the cache.
+#[derive(Default)]
+pub struct Cache {
+    entries: HashMap<(u64, u64), Entry>,
+}
+
+/// `$XDG_CACHE_HOME/so-lookup/cache.json`, or the same under `~/.cache`
+pub fn default_path() -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(dir.join("so-lookup").join("cache.json"))
+}
+
+impl Cache {
+    /// Reads the cache at `path`, starting from scratch if it is missing, unreadable or written by
+    /// another version
+    pub fn load(path: &Path) -> Self {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Self::default(),
+        };
+        match serde_json::from_reader::<_, CacheFile>(BufReader::new(file)) {
+            Ok(file) if file.version == VERSION => Self {
+                entries: file
+                    .entries
+                    .into_iter()
+                    .map(|entry| ((entry.stamp.dev, entry.stamp.ino), entry))
+                    .collect(),
+            },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn get(&self, stamp: &Stamp) -> Option<&Parsed> {
+        self.entries
+            .get(&(stamp.dev, stamp.ino))
+            .filter(|entry| entry.stamp == *stamp)
+            .map(|entry| &entry.parsed)
+    }
+
+    pub fn insert(&mut self, stamp: Stamp, parsed: Parsed) {
+        self.entries
+            .insert((stamp.dev, stamp.ino), Entry { stamp, parsed });
+    }
+
+    /// Writes the cache to `path`, replacing the old one only once the new one is complete
+    pub fn save(self, path: &Path) -> std::io::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let file = CacheFile {
+            version: VERSION,
+            entries: self.entries.into_values().collect(),
+        };
+        let mut output = BufWriter::new(File::create(&partial)?);
+        serde_json::to_writer(&mut output, &file)?;
+        output.flush()?;
+        std::fs::rename(partial, path)
+    }
+}
@@ -0,0 +1,119 @@
+use crate::model::Dependency;
+use crate::resolve::Resolver;
+use crate::symbols::{self, Symbols};
+use crate::{process_one, ElfInfo};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+struct Loaded {
+    info: Rc<ElfInfo>,
+    origin: PathBuf,
+    /// Index of the object whose DT_NEEDED entry caused this one to be loaded
+    loader: Option<usize>,
+}
+
+/// Computes dependency closures, parsing every library only once across all executables
+pub struct Loader<'a> {
+    resolver: &'a Resolver,
+    libraries: HashMap<PathBuf, Option<Rc<ElfInfo>>>,
+    symbols: HashMap<PathBuf, Option<Rc<Symbols>>>,
+}
+
+impl<'a> Loader<'a> {
+    pub fn new(resolver: &'a Resolver) -> Self {
+        Self {
+            resolver,
+            libraries: HashMap::new(),
+            symbols: HashMap::new(),
+        }
+    }
+
+    /// The library at the in-root `path`, parsed only once
+    pub fn library(&mut self, path: &Path) -> Option<Rc<ElfInfo>> {
+        let resolver = self.resolver;
+        self.libraries
+            .entry(path.to_path_buf())
+            .or_insert_with(|| process_one(&resolver.open_path(path)).ok().map(Rc::new))
+            .clone()
+    }
+
+    /// Dynamic symbols of the library at the in-root `path`, read only once
+    pub fn symbols(&mut self, path: &Path) -> Option<Rc<Symbols>> {
+        let resolver = self.resolver;
+        self.symbols
+            .entry(path.to_path_buf())
+            .or_insert_with(|| {
+                symbols::read(&resolver.open_path(path))
+                    .ok()
+                    .flatten()
+                    .map(Rc::new)
+            })
+            .clone()
+    }
+
+    /// Libraries loaded for the executable at `path`, in the breadth-first order ld.so loads them
+    /// in. Like ld.so, a DT_NEEDED entry matching the name or DT_SONAME of an already loaded
+    /// library does not load anything new.
+    pub fn closure(&mut self, path: &Path, executable: &ElfInfo) -> Vec<Dependency> {
+        let mut loaded = vec![Loaded {
+            info: Rc::new(executable.clone()),
+            origin: self.resolver.program_origin(path),
+            loader: None,
+        }];
+        let mut names = HashSet::new();
+        let mut paths = HashSet::new();
+        let mut dependencies = Vec::new();
+
+        // the program interpreter is mapped before anything else, so DT_NEEDED entries matching its
+        // DT_SONAME refer to it rather than to whatever the search path would find
+        let interpreter = executable.interpreter.as_ref().and_then(|interpreter| {
+            let path = PathBuf::from(interpreter);
+            let soname = self.library(&path)?.soname.clone()?;
+            Some((soname, path))
+        });
+
+        let mut next = 0;
+        while next < loaded.len() {
+            let requester = loaded[next].info.clone();
+            for soname in &requester.needed {
+                if !names.insert(soname.clone()) {
+                    continue;
+                }
+
+                let mut chain = Vec::new();
+                let mut index = Some(next);
+                while let Some(i) = index {
+                    chain.push((&*loaded[i].info, loaded[i].origin.as_path()));
+                    index = loaded[i].loader;
+                }
+                let resolved = match &interpreter {
+                    Some((interpreter, path)) if interpreter == soname => Some(path.clone()),
+                    _ => self.resolver.resolve(soname, &chain),
+                };
+
+                if let Some(resolved) = &resolved {
+                    // the same file reached through a different name is not loaded twice
+                    if !paths.insert(resolved.clone()) {
+                        continue;
+                    }
+                    if let Some(info) = self.library(resolved) {
+                        names.extend(info.soname.clone());
+                        loaded.push(Loaded {
+                            origin: self.resolver.library_origin(resolved),
+                            info,
+                            loader: Some(next),
+                        });
+                    }
+                }
+                dependencies.push(Dependency {
+                    soname: soname.clone(),
+                    path: resolved,
+                });
+            }
+            next += 1;
+        }
+
+        dependencies
+    }
+}
@@ -0,0 +1,178 @@
+use crate::model::Report;
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+
+const PALETTE: &[&str] = &[
+    "lightblue",
+    "lightsalmon",
+    "palegreen",
+    "plum",
+    "khaki",
+    "lightgray",
+    "pink",
+    "wheat",
+];
+
+/// How many member names a collapsed node lists before giving up
+const COLLAPSED_NAMES: usize = 3;
+
+#[derive(clap::Args, Debug)]
+#[clap(next_help_heading = "DOT OUTPUT")]
+pub struct Options {
+    /// Merge executables with identical DT_NEEDED entries into one node when there are at least
+    /// this many of them
+    #[clap(long, value_name = "COUNT")]
+    pub dot_collapse: Option<usize>,
+    /// Colour nodes by the e_machine of the binaries
+    #[clap(long)]
+    pub dot_color_machines: bool,
+    /// Only include nodes at most --dot-depth edges away from this soname
+    #[clap(long, value_name = "SONAME")]
+    pub dot_around: Option<String>,
+    #[clap(long, default_value = "1")]
+    pub dot_depth: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Node {
+    Executable(PathBuf),
+    /// Sonames of different machines are different libraries
+    Soname(String, String),
+}
+
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Writes the executable -> soname graph of DT_NEEDED entries in Graphviz format
+pub fn write(report: &Report, options: &Options, mut output: impl Write) -> std::io::Result<()> {
+    let mut machines = BTreeMap::new();
+    let mut edges = BTreeSet::new();
+    for (machine, entry) in &report.machines {
+        for (soname, entry) in &entry.sonames {
+            let library = Node::Soname(machine.clone(), soname.clone());
+            machines.insert(library.clone(), machine);
+            for consumer in &entry.consumers {
+                let executable = Node::Executable(consumer.path.clone());
+                machines.insert(executable.clone(), machine);
+                edges.insert((executable, library.clone()));
+            }
+        }
+    }
+
+    if let Some(around) = &options.dot_around {
+        let neighbours = edges
+            .iter()
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .into_group_map();
+        let mut kept = BTreeMap::new();
+        let mut queue = machines
+            .keys()
+            .filter(|node| matches!(node, Node::Soname(_, soname) if soname == around))
+            .map(|node| (node, 0))
+            .collect::<VecDeque<_>>();
+        while let Some((node, depth)) = queue.pop_front() {
+            if kept.insert(node.clone(), depth).is_some() || depth == options.dot_depth {
+                continue;
+            }
+            for &next in neighbours.get(node).into_iter().flatten() {
+                queue.push_back((next, depth + 1));
+            }
+        }
+        edges.retain(|(a, b)| kept.contains_key(a) && kept.contains_key(b));
+    }
+
+    // executables with exactly the same neighbours are indistinguishable in the graph
+    let mut names = BTreeMap::new();
+    let mut group_count = 0;
+    let by_libraries = edges
+        .iter()
+        .map(|(executable, library)| (executable, library))
+        .into_group_map();
+    let groups = by_libraries
+        .iter()
+        .map(|(&executable, libraries)| (libraries.iter().sorted().collect::<Vec<_>>(), executable))
+        .into_group_map();
+    for (libraries, executables) in groups.into_iter().sorted() {
+        let collapse = options
+            .dot_collapse
+            .is_some_and(|threshold| executables.len() >= threshold);
+        if collapse && executables.len() > 1 {
+            let mut label = format!("{} executables", executables.len());
+            for &executable in executables.iter().sorted().take(COLLAPSED_NAMES) {
+                if let Node::Executable(path) = executable {
+                    label.push_str(&format!("\n{}", path.display()));
+                }
+            }
+            if executables.len() > COLLAPSED_NAMES {
+                label.push_str("\n...");
+            }
+            group_count += 1;
+            let id = format!("group {}", group_count);
+            for &executable in &executables {
+                names.insert(executable.clone(), (id.clone(), label.clone()));
+            }
+        } else {
+            for &executable in &executables {
+                if let Node::Executable(path) = executable {
+                    let name = path.display().to_string();
+                    names.insert(executable.clone(), (name.clone(), name));
+                }
+            }
+        }
+        for &library in libraries {
+            if let Node::Soname(machine, soname) = library {
+                let id = format!("{}:{}", machine, soname);
+                names.insert(library.clone(), (id, soname.clone()));
+            }
+        }
+    }
+
+    let colors = report
+        .machines
+        .keys()
+        .zip(PALETTE.iter().cycle())
+        .collect::<BTreeMap<_, _>>();
+
+    writeln!(output, "digraph so_lookup {{")?;
+    writeln!(output, "    rankdir=LR;")?;
+    let mut written = BTreeSet::new();
+    for (node, (id, label)) in &names {
+        if !written.insert(id) {
+            continue;
+        }
+        let shape = match node {
+            Node::Executable(_) => "box",
+            Node::Soname(_, _) => "ellipse",
+        };
+        write!(
+            output,
+            "    {} [label={}, shape={}",
+            quote(id),
+            quote(label),
+            shape
+        )?;
+        if options.dot_color_machines {
+            write!(
+                output,
+                ", style=filled, fillcolor={}",
+                colors[&machines[node]]
+            )?;
+        }
+        writeln!(output, "];")?;
+    }
+    let edges = edges
+        .iter()
+        .map(|(a, b)| (&names[a].0, &names[b].0))
+        .collect::<BTreeSet<_>>();
+    for (a, b) in edges {
+        writeln!(output, "    {} -> {};", quote(a), quote(b))?;
+    }
+    writeln!(output, "}}")
+}
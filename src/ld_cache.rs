@@ -0,0 +1,370 @@
+use goblin::elf::header::{
+    EM_AARCH64, EM_ARM, EM_IA_64, EM_MIPS, EM_PPC64, EM_RISCV, EM_S390, EM_SPARCV9, EM_X86_64,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const MAGIC_OLD: &[u8] = b"ld.so-1.7.0";
+const MAGIC_NEW: &[u8] = b"glibc-ld.so.cache1.1";
+
+const HEADER_OLD_SIZE: usize = 16;
+const ENTRY_OLD_SIZE: usize = 12;
+const HEADER_NEW_SIZE: usize = 48;
+const ENTRY_NEW_SIZE: usize = 24;
+
+const FLAG_TYPE_MASK: u32 = 0x00ff;
+const FLAG_ELF: u32 = 0x0001;
+const FLAG_ELF_LIBC6: u32 = 0x0003;
+const FLAG_REQUIRED_MASK: u32 = 0xff00;
+const FLAG_SPARC_LIB64: u32 = 0x0100;
+const FLAG_IA64_LIB64: u32 = 0x0200;
+const FLAG_X8664_LIB64: u32 = 0x0300;
+const FLAG_S390_LIB64: u32 = 0x0400;
+const FLAG_POWERPC_LIB64: u32 = 0x0500;
+const FLAG_MIPS64_LIBN64: u32 = 0x0700;
+const FLAG_X8664_LIBX32: u32 = 0x0800;
+const FLAG_ARM_LIBHF: u32 = 0x0900;
+const FLAG_AARCH64_LIB64: u32 = 0x0a00;
+const FLAG_ARM_LIBSF: u32 = 0x0b00;
+const FLAG_MIPS64_LIBN64_NAN2008: u32 = 0x0e00;
+const FLAG_RISCV_FLOAT_ABI_SOFT: u32 = 0x0f00;
+const FLAG_RISCV_FLOAT_ABI_DOUBLE: u32 = 0x1000;
+
+/// Offset of the flags byte in the new header, whose low bits give the endianness (glibc 2.33+)
+const FLAGS_OFFSET: usize = 28;
+const CACHE_ENDIAN_MASK: u8 = 3;
+const CACHE_BIG_ENDIAN: u8 = 3;
+
+#[derive(Debug)]
+struct Entry {
+    flags: u32,
+    hwcap: u64,
+    path: PathBuf,
+}
+
+/// The binary cache written by ldconfig, in either the old `ld.so-1.7.0` or the new
+/// `glibc-ld.so.cache1.1` layout (or the old one followed by the new one)
+#[derive(Debug, Default)]
+pub struct LdCache {
+    entries: HashMap<String, Vec<Entry>>,
+}
+
+/// The architecture bits of the entry flags ld.so accepts for objects of this machine and class
+fn required_flags(machine: u16, is_64: bool) -> &'static [u32] {
+    match (machine, is_64) {
+        (EM_X86_64, true) => &[FLAG_X8664_LIB64],
+        (EM_X86_64, false) => &[FLAG_X8664_LIBX32],
+        (EM_AARCH64, true) => &[FLAG_AARCH64_LIB64],
+        (EM_ARM, false) => &[FLAG_ARM_LIBHF, FLAG_ARM_LIBSF],
+        (EM_PPC64, true) => &[FLAG_POWERPC_LIB64],
+        (EM_S390, true) => &[FLAG_S390_LIB64],
+        (EM_IA_64, true) => &[FLAG_IA64_LIB64],
+        (EM_SPARCV9, true) => &[FLAG_SPARC_LIB64],
+        (EM_MIPS, true) => &[FLAG_MIPS64_LIBN64, FLAG_MIPS64_LIBN64_NAN2008],
+        (EM_RISCV, true) => &[FLAG_RISCV_FLOAT_ABI_DOUBLE, FLAG_RISCV_FLOAT_ABI_SOFT],
+        (_, false) => &[0],
+        _ => &[],
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+fn read_u64(bytes: &[u8], offset: usize, big_endian: bool) -> Option<u64> {
+    let bytes = bytes.get(offset..offset + 8)?.try_into().ok()?;
+    Some(if big_endian {
+        u64::from_be_bytes(bytes)
+    } else {
+        u64::from_le_bytes(bytes)
+    })
+}
+
+fn read_str(bytes: &[u8], offset: usize) -> Option<&str> {
+    let bytes = bytes.get(offset..)?;
+    let len = bytes.iter().position(|&b| b == 0)?;
+    std::str::from_utf8(&bytes[..len]).ok()
+}
+
+impl LdCache {
+    /// Returns `None` if the file is missing or is not a cache in a known format
+    pub fn load(path: &Path) -> Option<Self> {
+        Self::parse(&std::fs::read(path).ok()?)
+    }
+
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(MAGIC_NEW) {
+            return Self::parse_new(bytes);
+        }
+        if !bytes.starts_with(MAGIC_OLD) {
+            return None;
+        }
+
+        // the old format is in the byte order of the machine ldconfig ran on, which only shows in
+        // the entry count fitting the file
+        let (big_endian, nlibs, entries_end) =
+            [false, true].into_iter().find_map(|big_endian| {
+                let nlibs = read_u32(bytes, MAGIC_OLD.len() + 1, big_endian)? as usize;
+                let entries_end =
+                    HEADER_OLD_SIZE.checked_add(nlibs.checked_mul(ENTRY_OLD_SIZE)?)?;
+                (entries_end <= bytes.len()).then_some((big_endian, nlibs, entries_end))
+            })?;
+        // the new format is appended after the old one, aligned like its 64-bit fields
+        let new_start = (entries_end + 7) & !7;
+        if bytes
+            .get(new_start..)
+            .is_some_and(|new| new.starts_with(MAGIC_NEW))
+        {
+            return Self::parse_new(&bytes[new_start..]);
+        }
+
+        // in the old format strings are relative to the end of the entry array
+        let strings = bytes.get(entries_end..)?;
+        let mut cache = Self::default();
+        for i in 0..nlibs {
+            let entry = HEADER_OLD_SIZE + i * ENTRY_OLD_SIZE;
+            cache.insert(
+                read_u32(bytes, entry, big_endian)?,
+                0,
+                read_str(strings, read_u32(bytes, entry + 4, big_endian)? as usize)?,
+                read_str(strings, read_u32(bytes, entry + 8, big_endian)? as usize)?,
+            );
+        }
+        Some(cache)
+    }
+
+    fn parse_new(bytes: &[u8]) -> Option<Self> {
+        let big_endian = bytes.get(FLAGS_OFFSET)? & CACHE_ENDIAN_MASK == CACHE_BIG_ENDIAN;
+        let nlibs = read_u32(bytes, MAGIC_NEW.len(), big_endian)? as usize;
+
+        // in the new format strings are relative to the start of the header
+        let mut cache = Self::default();
+        for i in 0..nlibs {
+            let entry = HEADER_NEW_SIZE + i * ENTRY_NEW_SIZE;
+            cache.insert(
+                read_u32(bytes, entry, big_endian)?,
+                read_u64(bytes, entry + 16, big_endian)?,
+                read_str(bytes, read_u32(bytes, entry + 4, big_endian)? as usize)?,
+                read_str(bytes, read_u32(bytes, entry + 8, big_endian)? as usize)?,
+            );
+        }
+        Some(cache)
+    }
+
+    fn insert(&mut self, flags: u32, hwcap: u64, soname: &str, path: &str) {
+        self.entries
+            .entry(soname.to_string())
+            .or_default()
+            .push(Entry {
+                flags,
+                hwcap,
+                path: PathBuf::from(path),
+            });
+    }
+
+    /// Finds the entry ld.so would use for an object of the given machine and class. Entries for
+    /// hwcap subdirectories depend on the CPU the program runs on, so the baseline one is preferred.
+    pub fn lookup(&self, soname: &str, machine: u16, is_64: bool) -> Option<&Path> {
+        let required = required_flags(machine, is_64);
+        let mut candidates = self.entries.get(soname)?.iter().filter(|e| {
+            matches!(e.flags & FLAG_TYPE_MASK, FLAG_ELF | FLAG_ELF_LIBC6)
+                && required.contains(&(e.flags & FLAG_REQUIRED_MASK))
+        });
+
+        let first = candidates.next()?;
+        let baseline = std::iter::once(first)
+            .chain(candidates)
+            .find(|e| e.hwcap == 0);
+        Some(&baseline.unwrap_or(first).path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goblin::elf::header::EM_386;
+
+    const X8664: u32 = FLAG_ELF_LIBC6 | FLAG_X8664_LIB64;
+
+    fn push_u32(bytes: &mut Vec<u8>, value: u32, big_endian: bool) {
+        bytes.extend_from_slice(&if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+
+    fn push_u64(bytes: &mut Vec<u8>, value: u64, big_endian: bool) {
+        bytes.extend_from_slice(&if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        });
+    }
+
+    /// Strings of `entries` one after the other, with the offsets of each soname and path
+    fn strings(entries: &[(u32, u64, &str, &str)], base: usize) -> (Vec<u8>, Vec<(u32, u32)>) {
+        let mut strings = Vec::new();
+        let mut offsets = Vec::new();
+        for (_, _, soname, path) in entries {
+            let soname_offset = (base + strings.len()) as u32;
+            strings.extend_from_slice(soname.as_bytes());
+            strings.push(0);
+            let path_offset = (base + strings.len()) as u32;
+            strings.extend_from_slice(path.as_bytes());
+            strings.push(0);
+            offsets.push((soname_offset, path_offset));
+        }
+        (strings, offsets)
+    }
+
+    /// A cache in the old layout, whose entries have no hwcap
+    fn old_cache(entries: &[(u32, u64, &str, &str)], big_endian: bool) -> Vec<u8> {
+        let mut bytes = MAGIC_OLD.to_vec();
+        bytes.resize(MAGIC_OLD.len() + 1, 0);
+        push_u32(&mut bytes, entries.len() as u32, big_endian);
+        let (strings, offsets) = strings(entries, 0);
+        for ((flags, _, _, _), (soname, path)) in entries.iter().zip(offsets) {
+            push_u32(&mut bytes, *flags, big_endian);
+            push_u32(&mut bytes, soname, big_endian);
+            push_u32(&mut bytes, path, big_endian);
+        }
+        bytes.extend_from_slice(&strings);
+        bytes
+    }
+
+    fn new_cache(entries: &[(u32, u64, &str, &str)], big_endian: bool) -> Vec<u8> {
+        let mut bytes = MAGIC_NEW.to_vec();
+        push_u32(&mut bytes, entries.len() as u32, big_endian);
+        let (strings, offsets) = strings(entries, HEADER_NEW_SIZE + entries.len() * ENTRY_NEW_SIZE);
+        push_u32(&mut bytes, strings.len() as u32, big_endian);
+        bytes.push(if big_endian { 3 } else { 2 });
+        bytes.resize(HEADER_NEW_SIZE, 0);
+        for ((flags, hwcap, _, _), (soname, path)) in entries.iter().zip(offsets) {
+            push_u32(&mut bytes, *flags, big_endian);
+            push_u32(&mut bytes, soname, big_endian);
+            push_u32(&mut bytes, path, big_endian);
+            push_u32(&mut bytes, 0, big_endian);
+            push_u64(&mut bytes, *hwcap, big_endian);
+        }
+        bytes.extend_from_slice(&strings);
+        bytes
+    }
+
+    /// The old layout followed by the new one, as ldconfig writes with `-c compat`
+    fn compat_cache(
+        old: &[(u32, u64, &str, &str)],
+        new: &[(u32, u64, &str, &str)],
+        big_endian: bool,
+    ) -> Vec<u8> {
+        let mut bytes = old_cache(old, big_endian);
+        bytes.resize(HEADER_OLD_SIZE + old.len() * ENTRY_OLD_SIZE, 0);
+        bytes.resize((bytes.len() + 7) & !7, 0);
+        bytes.extend_from_slice(&new_cache(new, big_endian));
+        bytes
+    }
+
+    fn lookup(bytes: &[u8], machine: u16, is_64: bool) -> Option<PathBuf> {
+        let cache = LdCache::parse(bytes)?;
+        cache
+            .lookup("libfoo.so.1", machine, is_64)
+            .map(Path::to_path_buf)
+    }
+
+    #[test]
+    fn old_layout() {
+        for big_endian in [false, true] {
+            let entries = [
+                (X8664, 0, "libbar.so.2", "/usr/lib64/libbar.so.2"),
+                (X8664, 0, "libfoo.so.1", "/usr/lib64/libfoo.so.1"),
+            ];
+            let bytes = old_cache(&entries, big_endian);
+            assert_eq!(
+                lookup(&bytes, EM_X86_64, true),
+                Some(PathBuf::from("/usr/lib64/libfoo.so.1"))
+            );
+        }
+    }
+
+    #[test]
+    fn new_layout() {
+        for big_endian in [false, true] {
+            let entries = [
+                (X8664, 0, "libbar.so.2", "/usr/lib64/libbar.so.2"),
+                (X8664, 0, "libfoo.so.1", "/usr/lib64/libfoo.so.1"),
+            ];
+            let bytes = new_cache(&entries, big_endian);
+            assert_eq!(
+                lookup(&bytes, EM_X86_64, true),
+                Some(PathBuf::from("/usr/lib64/libfoo.so.1"))
+            );
+        }
+    }
+
+    #[test]
+    fn old_layout_followed_by_new_one() {
+        for big_endian in [false, true] {
+            let old = [(X8664, 0, "libfoo.so.1", "/old/libfoo.so.1")];
+            let new = [
+                (X8664, 0, "libbar.so.2", "/new/libbar.so.2"),
+                (X8664, 0, "libfoo.so.1", "/new/libfoo.so.1"),
+            ];
+            let bytes = compat_cache(&old, &new, big_endian);
+            assert_eq!(
+                lookup(&bytes, EM_X86_64, true),
+                Some(PathBuf::from("/new/libfoo.so.1"))
+            );
+        }
+    }
+
+    #[test]
+    fn lookup_matches_the_machine() {
+        let entries = [
+            (X8664, 0, "libfoo.so.1", "/usr/lib64/libfoo.so.1"),
+            (
+                FLAG_ELF_LIBC6 | FLAG_X8664_LIBX32,
+                0,
+                "libfoo.so.1",
+                "/usr/libx32/libfoo.so.1",
+            ),
+            (FLAG_ELF_LIBC6, 0, "libfoo.so.1", "/usr/lib32/libfoo.so.1"),
+        ];
+        let bytes = new_cache(&entries, false);
+        assert_eq!(
+            lookup(&bytes, EM_X86_64, true),
+            Some(PathBuf::from("/usr/lib64/libfoo.so.1"))
+        );
+        assert_eq!(
+            lookup(&bytes, EM_X86_64, false),
+            Some(PathBuf::from("/usr/libx32/libfoo.so.1"))
+        );
+        assert_eq!(
+            lookup(&bytes, EM_386, false),
+            Some(PathBuf::from("/usr/lib32/libfoo.so.1"))
+        );
+        assert_eq!(lookup(&bytes, EM_AARCH64, true), None);
+    }
+
+    #[test]
+    fn lookup_prefers_the_baseline_entry() {
+        let entries = [
+            (
+                X8664,
+                1 << 62,
+                "libfoo.so.1",
+                "/usr/lib64/haswell/libfoo.so.1",
+            ),
+            (X8664, 0, "libfoo.so.1", "/usr/lib64/libfoo.so.1"),
+        ];
+        let bytes = new_cache(&entries, false);
+        assert_eq!(
+            lookup(&bytes, EM_X86_64, true),
+            Some(PathBuf::from("/usr/lib64/libfoo.so.1"))
+        );
+    }
+}
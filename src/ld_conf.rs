@@ -0,0 +1,74 @@
+use crate::sysroot::Sysroot;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// Directories listed in ld.so.conf (an in-root path), following `include` directives the way
+/// ldconfig does. Legacy `hwcap` lines are ignored, same as in current glibc.
+pub fn parse(sysroot: &Sysroot, path: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    parse_into(sysroot, path, &mut HashSet::new(), &mut dirs);
+    dirs
+}
+
+fn parse_into(
+    sysroot: &Sysroot,
+    path: &Path,
+    visited: &mut HashSet<PathBuf>,
+    dirs: &mut Vec<PathBuf>,
+) {
+    if !visited.insert(path.to_path_buf()) {
+        return;
+    }
+    let content = match std::fs::read_to_string(sysroot.open_path(path)) {
+        Ok(content) => content,
+        Err(_) => return,
+    };
+
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => {}
+            Some("include") => {
+                for pattern in words {
+                    for include in glob_in_root(sysroot, &relative_to(path, pattern)) {
+                        parse_into(sysroot, &include, visited, dirs);
+                    }
+                }
+            }
+            Some("hwcap") => {}
+            Some(_) => {
+                for dir in line.split(|c: char| c.is_whitespace() || c == ':' || c == ',') {
+                    // libc5-era "dir=type" syntax
+                    let dir = dir.split('=').next().unwrap_or_default();
+                    let dir = dir.trim_end_matches('/');
+                    if !dir.is_empty() && !dirs.iter().any(|d| d == Path::new(dir)) {
+                        dirs.push(PathBuf::from(dir));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Relative include patterns are relative to the directory of the including file
+fn relative_to(path: &Path, pattern: &str) -> PathBuf {
+    match path.parent() {
+        Some(parent) if !pattern.starts_with('/') => parent.join(pattern),
+        _ => PathBuf::from(pattern),
+    }
+}
+
+fn glob_in_root(sysroot: &Sysroot, pattern: &Path) -> Vec<PathBuf> {
+    let pattern = sysroot.host(pattern);
+    let paths = match pattern.to_str().map(glob::glob) {
+        Some(Ok(paths)) => paths,
+        _ => return Vec::new(),
+    };
+
+    // glob already yields paths in sorted order, same as glob(3) used by ldconfig
+    paths
+        .filter_map(|p| p.ok())
+        .filter_map(|p| sysroot.in_root(&p))
+        .collect()
+}
@@ -0,0 +1,65 @@
+use crate::closure::Loader;
+use crate::resolve::Resolver;
+use crate::{process_one, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// Prints what `ldd` would for each file and returns the exit status it would have
+pub fn run(resolver: &Resolver, files: &[PathBuf], addresses: bool) -> i32 {
+    let mut loader = Loader::new(resolver);
+    let address = if addresses {
+        " (0x0000000000000000)"
+    } else {
+        ""
+    };
+
+    let mut status = 0;
+    for file in files {
+        if files.len() > 1 {
+            println!("{}:", file.display());
+        }
+
+        let info = match process_one(file) {
+            Ok(info) => info,
+            Err(ErrorKind::CannotRead(e)) => {
+                eprintln!("ldd: {}: {}", file.display(), e);
+                status = 1;
+                continue;
+            }
+            Err(_) => {
+                eprintln!("\tnot a dynamic executable");
+                status = 1;
+                continue;
+            }
+        };
+
+        // like for static-pie executables, glibc's ldd has nothing to list for objects that
+        // neither need anything nor name an interpreter
+        if info.interpreter.is_none() && info.needed.is_empty() {
+            println!("\tstatically linked");
+            continue;
+        }
+
+        let interpreter = info
+            .interpreter
+            .as_ref()
+            .map(|interpreter| (interpreter, Path::new(interpreter)));
+        let mut interpreter_printed = false;
+        for dependency in loader.closure(file, &info) {
+            match (&dependency.path, &interpreter) {
+                (Some(path), Some((name, interpreter))) if path == interpreter => {
+                    println!("\t{}{}", name, address);
+                    interpreter_printed = true;
+                }
+                (Some(path), _) => {
+                    println!("\t{} => {}{}", dependency.soname, path.display(), address)
+                }
+                (None, _) => println!("\t{} => not found", dependency.soname),
+            }
+        }
+        if let (Some((name, _)), false) = (&interpreter, interpreter_printed) {
+            println!("\t{}{}", name, address);
+        }
+    }
+
+    status
+}
@@ -1,109 +1,931 @@
-use crate::ErrorKind::{CannotRead, NotAnElf, NotDynamic, StrtableBad};
-use clap::Parser;
-use goblin::elf::dynamic::{DT_STRSZ, DT_STRTAB};
+mod cache;
+mod closure;
+mod dot;
+mod ld_cache;
+mod ld_conf;
+mod ldd;
+mod model;
+mod parse;
+mod resolve;
+mod sqlite;
+mod symbols;
+mod sysroot;
+mod text;
+mod versions;
+
+use crate::cache::{Cache, Parsed, Stamp};
+use crate::closure::Loader;
+use crate::model::{Consumer, Kind, Report, Static};
+use crate::parse::parse_dynamic;
+use crate::resolve::Resolver;
+use crate::sysroot::Sysroot;
+use crate::versions::Need;
+use crate::ErrorKind::{
+    CannotRead, MissingDynTag, NonUtf8Path, NotAnElf, NotDynamic, StaticExecutable, StrtableBad,
+};
+use clap::{ArgEnum, Parser, Subcommand};
+use goblin::container::Ctx;
+use goblin::elf::dynamic::{
+    tag_to_str, Dynamic, DF_1_NODEFLIB, DF_1_PIE, DT_RPATH, DT_RUNPATH, DT_SONAME, DT_STRSZ,
+    DT_STRTAB,
+};
+use goblin::elf::header::{EI_CLASS, ELFCLASS64, ELFMAG, ET_DYN, ET_EXEC, SELFMAG};
+use goblin::elf::program_header::{ProgramHeader, PT_INTERP, PT_LOAD};
 use goblin::elf32::header::machine_to_str;
 use goblin::strtab::Strtab;
-use indicatif::ProgressIterator;
+use indicatif::ProgressBar;
 use itertools::Itertools;
-use std::collections::BTreeMap;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, Read, Write};
 use std::os::unix::fs::PermissionsExt;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
+use walkdir::{DirEntry, WalkDir};
 
 /// Program to analyze which executables are using which shared libraries
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+    #[clap(short, long, required = true)]
+    executables_dir: Option<PathBuf>,
+    /// Root of the filesystem the executables belong to; ld.so.conf, ld.so.cache and the default
+    /// library directories are looked up inside it
+    #[clap(long, global = true, default_value = "/")]
+    sysroot: PathBuf,
+    /// Colon-separated list of directories searched as if LD_LIBRARY_PATH was set
+    #[clap(long, global = true, default_value = "")]
+    ld_library_path: String,
+    /// Select files by the ELF magic at their start instead of the owner execute bit
+    #[clap(long)]
+    elf_magic: bool,
+    /// ELF types selected by --elf-magic
+    #[clap(long, arg_enum, value_delimiter = ',', default_value = "exec,pie")]
+    elf_types: Vec<ElfType>,
+    /// Also scan shared libraries and plugins as consumers; implies --elf-magic
+    #[clap(long)]
+    libraries: bool,
+    /// Number of threads parsing files, one per CPU by default
+    #[clap(short, long)]
+    jobs: Option<usize>,
+    /// File remembering parse results between runs, by default so-lookup/cache.json in
+    /// $XDG_CACHE_HOME or ~/.cache
+    #[clap(long, value_name = "FILE")]
+    cache: Option<PathBuf>,
+    /// Neither read nor update the cache
+    #[clap(long, conflicts_with = "rebuild-cache")]
+    no_cache: bool,
+    /// Parse every file again and replace the cache with the results
+    #[clap(long)]
+    rebuild_cache: bool,
+    /// Report files that are duplicates of one found earlier only as its aliases
+    #[clap(long, arg_enum)]
+    dedup: Option<Dedup>,
+    /// Exit with a non-zero status if some executable has a DT_NEEDED entry that cannot be found
+    #[clap(long)]
+    fail_on_missing: bool,
+    /// Also compute the full transitive dependency closure of every executable
+    #[clap(long)]
+    transitive: bool,
+    /// Also list the undefined symbols of every executable by the library providing them
+    #[clap(long)]
+    symbols: bool,
+    /// Also list the DT_NEEDED entries of every executable that none of its undefined symbols are
+    /// found in
+    #[clap(long)]
+    unused: bool,
+    /// Also list the undefined symbols of every executable that are only found in indirect
+    /// dependencies
+    #[clap(long)]
+    underlinked: bool,
+    /// Also list the undefined symbols of every executable that no library of its closure
+    /// provides, taking symbol versions into account
+    #[clap(long)]
+    unresolved: bool,
+    /// File of soname patterns, one per line, never reported by --unused
+    #[clap(long, value_name = "FILE", requires = "unused")]
+    unused_allowlist: Option<PathBuf>,
+    #[clap(long, arg_enum, default_value = "text")]
+    format: Format,
+    /// Directory for the text reports (current directory by default), or file for the other
+    /// formats (standard output by default)
     #[clap(short, long)]
-    executables_dir: PathBuf,
+    output: Option<PathBuf>,
+    /// Also list every executable that was skipped, with the reason, in this file
+    #[clap(long, value_name = "FILE")]
+    skipped: Option<PathBuf>,
+    #[clap(flatten)]
+    dot: dot::Options,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Format {
+    /// m_{machine}.txt and similar files
+    Text,
+    Json,
+    /// Graphviz graph of executables and the sonames they need
+    Dot,
+    /// SQLite database, written to so-lookup.sqlite unless --output is given
+    Sqlite,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum ElfType {
+    /// ET_EXEC, executables at a fixed address
+    Exec,
+    /// ET_DYN other than PIE, shared libraries and plugins
+    Dyn,
+    /// ET_DYN position independent executables
+    Pie,
+}
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Dedup {
+    /// Hard links to the same file
+    Inode,
+    /// Files with the same contents
+    Content,
+}
+
+/// What makes two files duplicates for --dedup
+#[derive(PartialEq, Eq, Hash)]
+enum Identity {
+    Inode(u64, u64),
+    Content([u8; 32]),
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the libraries a program would load in the format of ldd, without running it
+    Ldd {
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+        /// Print a zero load address after every library, for scripts expecting one
+        #[clap(short, long)]
+        addresses: bool,
+    },
 }
 
 #[derive(Debug)]
 enum ErrorKind {
     CannotRead(std::io::Error),
     NotAnElf(goblin::error::Error),
-    NotDynamic,
+    /// A file without a dynamic section that is not an executable either, like relocatable
+    /// objects and core dumps
+    NotDynamic {
+        elf_type: u16,
+    },
+    /// An executable without a dynamic section, which ld.so is never involved with
+    StaticExecutable {
+        machine: u16,
+    },
+    /// A dynamic tag the file cannot do without, like DT_STRTAB
+    MissingDynTag(u64),
     StrtableBad(goblin::error::Error),
+    /// The path cannot be represented in the reports
+    NonUtf8Path,
 }
 
-fn process_one(path: &Path) -> Result<(u16, Vec<String>), ErrorKind> {
-    let file = std::fs::read(&path).map_err(CannotRead)?;
-    let elf = goblin::elf::Elf::parse(&file).map_err(NotAnElf)?;
-    let dynamic = elf.dynamic.ok_or(NotDynamic)?;
+impl ErrorKind {
+    /// Short description shared by all errors of the same kind
+    fn reason(&self) -> &'static str {
+        match self {
+            CannotRead(_) => "cannot be read",
+            NotAnElf(_) => "not an ELF file",
+            NotDynamic { .. } => "not dynamically linked",
+            StaticExecutable { .. } => "statically linked",
+            MissingDynTag(_) => "missing a dynamic tag",
+            StrtableBad(_) => "bad dynamic string table",
+            NonUtf8Path => "non-UTF-8 path",
+        }
+    }
+}
 
-    let dyn_strtable = dynamic
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.reason())?;
+        match self {
+            CannotRead(e) => write!(f, ": {}", e),
+            NotAnElf(e) | StrtableBad(e) => write!(f, ": {}", e),
+            MissingDynTag(tag) => write!(f, ": {}", tag_to_str(*tag)),
+            NotDynamic { .. } | StaticExecutable { .. } | NonUtf8Path => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ElfInfo {
+    pub machine: u16,
+    pub soname: Option<String>,
+    pub interpreter: Option<String>,
+    pub is_64: bool,
+    pub needed: Vec<String>,
+    pub rpath: Vec<String>,
+    pub runpath: Vec<String>,
+    pub nodeflib: bool,
+    pub elf_type: u16,
+    /// ET_DYN meant to be run rather than loaded as a library
+    pub pie: bool,
+    /// Position independent executable loading itself, without an interpreter or any DT_NEEDED
+    pub static_pie: bool,
+    /// Symbol versions required from each DT_NEEDED entry, from DT_VERNEED
+    #[serde(default)]
+    pub version_needs: BTreeMap<String, Vec<Need>>,
+    /// Symbol versions defined, from DT_VERDEF, leaving out the base version naming the object
+    #[serde(default)]
+    pub version_defs: Vec<String>,
+}
+
+fn search_dirs(dynamic: &Dynamic, table: &Strtab, tag: u64) -> Vec<String> {
+    dynamic
         .dyns
         .iter()
-        .find(|t| t.d_tag == DT_STRTAB)
-        .map(|t| t.d_val)
-        .unwrap();
-    let dyn_strtable_size = dynamic
+        .filter(|t| t.d_tag == tag)
+        .filter_map(|t| table.get_at(t.d_val as usize))
+        .flat_map(|p| p.split(':'))
+        .filter(|p| !p.is_empty())
+        .map(|p| p.to_string())
+        .collect()
+}
+
+/// Value of the first dynamic entry with `tag`
+fn dynamic_tag(dynamic: &Dynamic, tag: u64) -> Option<u64> {
+    dynamic
         .dyns
         .iter()
-        .find(|t| t.d_tag == DT_STRSZ)
+        .find(|t| t.d_tag == tag)
         .map(|t| t.d_val)
-        .unwrap();
-    let table = Strtab::parse(&file, dyn_strtable as usize, dyn_strtable_size as usize, 0)
-        .map_err(StrtableBad)?;
-
-    Ok((
-        elf.header.e_machine,
-        dynamic
-            .get_libraries(&table)
-            .into_iter()
-            .map(|l| l.to_string())
-            .collect(),
-    ))
+}
+
+/// File offset of the byte mapped at virtual address `vaddr`, if any PT_LOAD segment maps it
+fn vaddr_to_offset(program_headers: &[ProgramHeader], vaddr: u64) -> Option<u64> {
+    program_headers
+        .iter()
+        .filter(|ph| ph.p_type == PT_LOAD)
+        // p_vaddr + p_filesz may overflow in a crafted file, the difference cannot
+        .find(|ph| ph.p_vaddr <= vaddr && vaddr - ph.p_vaddr < ph.p_filesz)
+        .and_then(|ph| (vaddr - ph.p_vaddr).checked_add(ph.p_offset))
+}
+
+fn interpreter(bytes: &[u8], program_headers: &[ProgramHeader]) -> Option<String> {
+    let ph = program_headers
+        .iter()
+        .find(|ph| ph.p_type == PT_INTERP && ph.p_filesz != 0)?;
+    // without the terminating NUL
+    let path = bytes
+        .get(ph.p_offset as usize..)?
+        .get(..ph.p_filesz as usize - 1)?;
+    std::str::from_utf8(path).ok().map(|s| s.to_string())
+}
+
+/// The string table DT_NEEDED, DT_SONAME and the dynamic symbols refer to
+fn dynamic_strtab<'a>(
+    bytes: &'a [u8],
+    program_headers: &[ProgramHeader],
+    dynamic: &Dynamic,
+) -> Result<Strtab<'a>, ErrorKind> {
+    let dyn_strtable = dynamic_tag(dynamic, DT_STRTAB).ok_or(MissingDynTag(DT_STRTAB))?;
+    let dyn_strtable_size = dynamic_tag(dynamic, DT_STRSZ).ok_or(MissingDynTag(DT_STRSZ))?;
+    // DT_STRTAB is a virtual address, which only coincides with the file offset when the first
+    // segment is mapped at 0
+    let dyn_strtable = vaddr_to_offset(program_headers, dyn_strtable).ok_or_else(|| {
+        StrtableBad(goblin::error::Error::Malformed(format!(
+            "DT_STRTAB {:#x} is not mapped by any PT_LOAD segment",
+            dyn_strtable
+        )))
+    })?;
+    Strtab::parse(bytes, dyn_strtable as usize, dyn_strtable_size as usize, 0).map_err(StrtableBad)
+}
+
+fn process_one(path: &Path) -> Result<ElfInfo, ErrorKind> {
+    let file = File::open(path).map_err(CannotRead)?;
+    // only the parts parsed below are ever read from disk, which matters for huge binaries. Like
+    // for any other mmap user, a file truncated while mapped is fatal.
+    let file = unsafe { Mmap::map(&file) }.map_err(CannotRead)?;
+    let (header, program_headers, dynamic) = parse_dynamic(&file).map_err(NotAnElf)?;
+    let dynamic = match dynamic {
+        Some(dynamic) => dynamic,
+        None if header.e_type == ET_EXEC => {
+            return Err(StaticExecutable {
+                machine: header.e_machine,
+            })
+        }
+        None => {
+            return Err(NotDynamic {
+                elf_type: header.e_type,
+            })
+        }
+    };
+    let interpreter = interpreter(&file, &program_headers);
+
+    let table = dynamic_strtab(&file, &program_headers, &dynamic)?;
+
+    let needed = dynamic
+        .get_libraries(&table)
+        .into_iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>();
+    // linkers predating DF_1_PIE leave only the entry point to tell such executables from
+    // libraries. Plugins may have one too, so that only counts alongside a PT_INTERP.
+    let flagged_pie = header.e_type == ET_DYN && dynamic.info.flags_1 & DF_1_PIE != 0;
+    let pie = flagged_pie
+        || header.e_type == ET_DYN
+            && interpreter.is_some()
+            && header.e_entry != 0
+            && dynamic.dyns.iter().all(|t| t.d_tag != DT_SONAME);
+    let static_pie = flagged_pie && interpreter.is_none() && needed.is_empty();
+    let ctx = Ctx::new(
+        header.container().map_err(NotAnElf)?,
+        header.endianness().map_err(NotAnElf)?,
+    );
+    let versions = versions::read(&file, &program_headers, &dynamic, &table, ctx);
+
+    Ok(ElfInfo {
+        machine: header.e_machine,
+        soname: dynamic
+            .dyns
+            .iter()
+            .find(|t| t.d_tag == DT_SONAME)
+            .and_then(|t| table.get_at(t.d_val as usize))
+            .map(|s| s.to_string()),
+        interpreter,
+        is_64: header.e_ident[EI_CLASS] == ELFCLASS64,
+        needed,
+        rpath: search_dirs(&dynamic, &table, DT_RPATH),
+        runpath: search_dirs(&dynamic, &table, DT_RUNPATH),
+        nodeflib: dynamic.info.flags_1 & DF_1_NODEFLIB != 0,
+        elf_type: header.e_type,
+        pie,
+        static_pie,
+        version_needs: versions.needs,
+        version_defs: versions.defs,
+    })
+}
+
+/// Files worth parsing are regular files with either the owner execute bit set or, when
+/// `elf_magic` is given, the ELF magic at their start
+fn is_candidate(entry: &DirEntry, elf_magic: bool) -> Result<bool, ErrorKind> {
+    let m = entry.metadata().map_err(|e| CannotRead(e.into()))?;
+    if !m.is_file() {
+        return Ok(false);
+    }
+    if !elf_magic {
+        return Ok(m.permissions().mode() & 0o100 != 0);
+    }
+
+    let mut magic = [0; SELFMAG];
+    match File::open(entry.path()).and_then(|mut file| file.read_exact(&mut magic)) {
+        Ok(()) => Ok(magic == *ELFMAG),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(CannotRead(e)),
+    }
+}
+
+/// Whether a parsed file is of one of the --elf-types. Relocatable objects, core dumps and the like
+/// never are, files whose type cannot be told are kept to be reported as skipped.
+fn is_selected(res: &Result<ElfInfo, ErrorKind>, elf_types: &[ElfType]) -> bool {
+    let elf_type = match res {
+        Ok(info) if info.pie => ElfType::Pie,
+        Ok(info) if info.elf_type == ET_EXEC => ElfType::Exec,
+        Ok(_) => ElfType::Dyn,
+        Err(StaticExecutable { .. }) => ElfType::Exec,
+        Err(NotDynamic { elf_type: ET_DYN }) => ElfType::Dyn,
+        Err(NotDynamic { .. }) => return false,
+        Err(_) => return true,
+    };
+    elf_types.contains(&elf_type)
+}
+
+/// Shared objects without a DT_SONAME cannot be found by ld.so through DT_NEEDED entries, so
+/// they are only ever loaded by dlopen
+fn consumer_kind(info: &ElfInfo) -> Kind {
+    if info.pie || info.elf_type == ET_EXEC {
+        Kind::Executable
+    } else if info.soname.is_some() {
+        Kind::SharedLibrary
+    } else {
+        Kind::Plugin
+    }
+}
+
+fn content_hash(path: &Path) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+struct Scanned {
+    path: PathBuf,
+    size: u64,
+    stamp: Option<Stamp>,
+    identity: Option<Identity>,
+    res: Result<ElfInfo, ErrorKind>,
+}
+
+/// Parses a file found by the directory walk, `None` if it is not selected for the scan
+fn scan_one(
+    entry: &DirEntry,
+    elf_magic: bool,
+    elf_types: &[ElfType],
+    dedup: Option<Dedup>,
+    cache: &Cache,
+) -> Option<Scanned> {
+    let f = entry.path().to_path_buf();
+    let metadata = entry.metadata().ok();
+    let stamp = metadata.as_ref().map(Stamp::new);
+    let res = match is_candidate(entry, elf_magic) {
+        Ok(false) => return None,
+        Ok(true) if f.to_str().is_none() => Err(NonUtf8Path),
+        Ok(true) => match stamp.as_ref().and_then(|stamp| cache.get(stamp)) {
+            Some(parsed) => parsed.clone().into_result(),
+            None => process_one(&f),
+        },
+        Err(e) => Err(e),
+    };
+    if elf_magic && !is_selected(&res, elf_types) {
+        return None;
+    }
+    // only files making it into the report can be aliases
+    let identity = match (dedup, &res) {
+        (_, Err(e)) if !matches!(e, StaticExecutable { .. }) => None,
+        (Some(Dedup::Inode), _) => stamp.map(|stamp| {
+            let (dev, ino) = stamp.inode();
+            Identity::Inode(dev, ino)
+        }),
+        (Some(Dedup::Content), _) => content_hash(&f).ok().map(Identity::Content),
+        (None, _) => None,
+    };
+    Some(Scanned {
+        path: f,
+        size: metadata.map_or(0, |m| m.len()),
+        stamp,
+        identity,
+        res,
+    })
+}
+
+/// A directory or file the walk could not get to, reported like a file that cannot be read
+fn walk_error(e: walkdir::Error, root: &Path) -> Scanned {
+    let path = e.path().unwrap_or(root).to_path_buf();
+    // the path is already part of the listing, walkdir would repeat it
+    let error = match e.io_error() {
+        Some(inner) => io::Error::new(inner.kind(), inner.to_string()),
+        None => e.into(),
+    };
+    Scanned {
+        path,
+        size: 0,
+        stamp: None,
+        identity: None,
+        res: Err(CannotRead(error)),
+    }
+}
+
+/// Writes the report in the selected format to `--output` or its default
+fn write_report(report: &Report, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    match args.format {
+        Format::Text => text::write(report, args.output.as_deref().unwrap_or(Path::new(".")))?,
+        Format::Json => match &args.output {
+            Some(output) => serde_json::to_writer_pretty(File::create(output)?, report)?,
+            None => serde_json::to_writer_pretty(std::io::stdout(), report)?,
+        },
+        Format::Dot => match &args.output {
+            Some(output) => dot::write(report, &args.dot, File::create(output)?)?,
+            None => dot::write(report, &args.dot, std::io::stdout())?,
+        },
+        Format::Sqlite => sqlite::write(
+            report,
+            args.output
+                .as_deref()
+                .unwrap_or(Path::new("so-lookup.sqlite")),
+        )?,
+    }
+    Ok(())
 }
 
 fn main() {
     let args = Args::parse();
 
-    let mut aboba = BTreeMap::new();
+    let resolver = Resolver::new(
+        Sysroot::new(args.sysroot.clone()),
+        args.ld_library_path
+            .split(':')
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .collect(),
+    );
+
+    let executables_dir = match &args.command {
+        Some(Command::Ldd { files, addresses }) => {
+            std::process::exit(ldd::run(&resolver, files, *addresses))
+        }
+        // clap only allows it to be missing when there is a subcommand
+        None => args.executables_dir.as_ref().unwrap(),
+    };
 
-    let tree = WalkDir::new(&args.executables_dir)
+    let mut report = Report::default();
+    // resolved libraries are parsed for their version definitions anyway, the rest only on request
+    let mut loader = Loader::new(&resolver);
+    let closures =
+        args.transitive || args.symbols || args.unused || args.underlinked || args.unresolved;
+    let allowed = match &args.unused_allowlist {
+        Some(path) => symbols::read_allowlist(path).unwrap_or_else(|e| {
+            eprintln!("cannot read {}: {}", path.display(), e);
+            std::process::exit(2)
+        }),
+        None => Vec::new(),
+    };
+    let mut skipped = Vec::new();
+
+    let elf_magic = args.elf_magic || args.libraries;
+    let mut elf_types = args.elf_types.clone();
+    if args.libraries {
+        elf_types.push(ElfType::Dyn);
+    }
+
+    let tree = WalkDir::new(executables_dir)
+        .sort_by_file_name()
         .into_iter()
         .collect::<Vec<_>>();
 
-    for f in tree
-        .into_iter()
-        .progress()
-        .filter_map(|f| f.ok())
-        .filter(|f| {
-            let m = f.metadata().unwrap();
-            m.is_file() && m.permissions().mode() & 0o100 != 0
-        })
-        .map(|f| f.path().to_path_buf())
-    {
-        let res = process_one(&f);
-        if let Ok((machine, res)) = res {
-            for lib in res {
-                let mentry = aboba.entry(machine);
-                let aboba = mentry.or_insert(BTreeMap::new());
-
-                let entry = aboba.entry(lib);
-                entry.or_insert(Vec::new()).push(f.clone());
+    let cache_path = match &args.cache {
+        _ if args.no_cache => None,
+        Some(path) => Some(path.clone()),
+        None => cache::default_path(),
+    };
+    let mut cache = match &cache_path {
+        Some(path) if !args.rebuild_cache => Cache::load(path),
+        _ => Cache::default(),
+    };
+
+    // parsing is what takes time and does not depend on anything else; collecting the results in
+    // walk order keeps the output independent of the number of threads
+    let progress = ProgressBar::new(tree.len() as u64);
+    let scanned = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.jobs.unwrap_or(0))
+        .build()
+        .unwrap()
+        .install(|| {
+            tree.into_par_iter()
+                .filter_map(|entry| {
+                    progress.inc(1);
+                    match entry {
+                        Ok(entry) => scan_one(&entry, elf_magic, &elf_types, args.dedup, &cache),
+                        Err(e) => Some(walk_error(e, executables_dir)),
+                    }
+                })
+                .collect::<Vec<_>>()
+        });
+    progress.finish();
+
+    // walk order decides which of the duplicates is the original
+    let mut originals = HashMap::<_, PathBuf>::new();
+    for scanned in scanned {
+        if let (Some(stamp), Some(parsed)) = (scanned.stamp, Parsed::new(&scanned.res)) {
+            cache.insert(stamp, parsed);
+        }
+        let Scanned {
+            path: f,
+            size,
+            identity,
+            res,
+            ..
+        } = scanned;
+        // reported like the libraries they resolve to, as seen from inside the sysroot
+        let path = resolver.in_root(&f);
+        if let Some(identity) = identity {
+            match originals.entry(identity) {
+                Entry::Occupied(original) => {
+                    report
+                        .aliases
+                        .entry(original.get().clone())
+                        .or_default()
+                        .push(path);
+                    continue;
+                }
+                Entry::Vacant(original) => {
+                    original.insert(path.clone());
+                }
+            }
+        }
+        let info = match res {
+            Ok(info) if info.static_pie => {
+                let machine = report.machine(machine_to_str(info.machine));
+                machine.statics.insert(path, Static { pie: true, size });
+                continue;
+            }
+            Ok(info) => info,
+            Err(StaticExecutable { machine }) => {
+                let machine = report.machine(machine_to_str(machine));
+                machine.statics.insert(path, Static { pie: false, size });
+                continue;
+            }
+            Err(e) => {
+                skipped.push((path, e));
+                continue;
             }
         };
+        let kind = consumer_kind(&info);
+        let machine = report.machine(machine_to_str(info.machine));
+
+        let origin = resolver.program_origin(&f);
+        for lib in &info.needed {
+            let resolved = resolver.resolve(lib, &[(&info, &origin)]);
+            let needs = info.version_needs.get(lib).map_or(&[][..], Vec::as_slice);
+            let defs = resolved
+                .as_ref()
+                .and_then(|resolved| loader.library(resolved))
+                .map(|library| library.version_defs.clone())
+                .unwrap_or_default();
+            let versions = needs.iter().map(|need| need.name.clone()).collect();
+            let missing_versions = needs
+                .iter()
+                .filter(|need| !need.weak && !defs.is_empty() && !defs.contains(&need.name))
+                .map(|need| need.name.clone())
+                .collect();
+            machine.add_consumer(
+                lib,
+                Consumer {
+                    path: path.clone(),
+                    kind,
+                    resolved,
+                    versions,
+                    missing_versions,
+                },
+            );
+        }
+
+        if closures {
+            let closure = loader.closure(&f, &info);
+            let imports = (args.symbols || args.unused || args.underlinked)
+                .then(|| symbols::imports(&mut loader, &f, &closure))
+                .flatten();
+            if let Some(imports) = imports {
+                if args.underlinked {
+                    let underlinked = symbols::underlinked(&info, &imports);
+                    if !underlinked.is_empty() {
+                        machine.underlinked.insert(path.clone(), underlinked);
+                    }
+                }
+                if args.unused {
+                    let unused = symbols::unused(&mut loader, &info, &closure, &imports, &allowed);
+                    if !unused.is_empty() {
+                        machine.unused.insert(path.clone(), unused);
+                    }
+                }
+                if args.symbols {
+                    machine.imports.insert(path.clone(), imports);
+                }
+            }
+            if args.unresolved {
+                let unresolved = symbols::unresolved(&mut loader, &f, &closure).unwrap_or_default();
+                if !unresolved.is_empty() {
+                    machine.unresolved.insert(path.clone(), unresolved);
+                }
+            }
+            if args.transitive {
+                machine.closures.insert(path.clone(), closure);
+            }
+        }
     }
 
-    for (machine, aboba) in aboba {
-        let machine = machine_to_str(machine);
+    if let Some(path) = &cache_path {
+        if let Err(e) = cache.save(path) {
+            eprintln!("cannot write the cache to {}: {}", path.display(), e);
+        }
+    }
 
-        let mut output = File::create(format!("m_{}.txt", machine)).unwrap();
+    if let Err(e) = write_report(&report, &args) {
+        let output = match (&args.output, args.format) {
+            (Some(output), _) => output.display().to_string(),
+            (None, Format::Text) => ".".to_string(),
+            (None, Format::Sqlite) => "so-lookup.sqlite".to_string(),
+            (None, _) => "standard output".to_string(),
+        };
+        eprintln!("cannot write the report to {}: {}", output, e);
+        std::process::exit(2)
+    }
 
-        for (soname, mut exes) in aboba
-            .into_iter()
-            .sorted_by_key(|(_, exes)| exes.len() as isize)
-            .rev()
-        {
-            writeln!(output, "{} ({} exes)", soname, exes.len()).unwrap();
-            exes.sort();
-            for exe in exes {
-                writeln!(output, "        <= {}", exe.to_str().unwrap()).unwrap();
+    if let Some(path) = &args.skipped {
+        let written = File::create(path).and_then(|mut output| {
+            for (f, e) in &skipped {
+                writeln!(output, "{}: {}", f.display(), e)?;
             }
+            Ok(())
+        });
+        if let Err(e) = written {
+            eprintln!("cannot write {}: {}", path.display(), e);
+            std::process::exit(2)
         }
     }
+    if !skipped.is_empty() {
+        let counts = skipped.iter().counts_by(|(_, e)| e.reason());
+        eprintln!("{} executables were skipped:", skipped.len());
+        for (reason, count) in counts.into_iter().collect::<BTreeMap<_, _>>() {
+            eprintln!("    {} {}", count, reason);
+        }
+    }
+
+    let broken = report
+        .machines
+        .values()
+        .flat_map(|machine| machine.missing().into_values().flatten())
+        .collect::<BTreeSet<_>>();
+    if !broken.is_empty() {
+        eprintln!(
+            "{} executables have DT_NEEDED entries that cannot be found",
+            broken.len()
+        );
+    }
+
+    let unresolved = report
+        .machines
+        .values()
+        .map(|machine| machine.unresolved.len())
+        .sum::<usize>();
+    if unresolved > 0 {
+        eprintln!(
+            "{} executables have undefined symbols that no library provides",
+            unresolved
+        );
+    }
+
+    let outdated = report
+        .machines
+        .values()
+        .flat_map(|machine| machine.sonames.values())
+        .flat_map(|entry| &entry.consumers)
+        .filter(|consumer| !consumer.missing_versions.is_empty())
+        .map(|consumer| &consumer.path)
+        .collect::<BTreeSet<_>>();
+    if !outdated.is_empty() {
+        eprintln!(
+            "{} executables require symbol versions their libraries do not define",
+            outdated.len()
+        );
+    }
+
+    if args.fail_on_missing && !broken.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use goblin::elf::dynamic::{DT_NEEDED, DT_NULL};
+    use goblin::elf::header::EM_X86_64;
+    use goblin::elf::program_header::PT_DYNAMIC;
+    use std::process::Command;
+
+    /// Writes `bytes` to a file of its own and parses it
+    fn process_bytes(name: &str, bytes: &[u8]) -> Result<ElfInfo, ErrorKind> {
+        let path = std::env::temp_dir().join(format!("so-lookup-{}-{}", std::process::id(), name));
+        std::fs::write(&path, bytes).unwrap();
+        let res = process_one(&path);
+        std::fs::remove_file(&path).unwrap();
+        res
+    }
+
+    /// A minimal little-endian ELF64 executable needing libfoo.so.1, with a single PT_LOAD
+    /// mapping the whole file at `vaddr`. DT_STRTAB is `strtab_delta` bytes away from the address
+    /// the string table is really mapped at.
+    fn executable(vaddr: u64, filesz: u64, strtab_delta: i64) -> Vec<u8> {
+        const PHDRS: u64 = 64;
+        const DYNAMIC: u64 = PHDRS + 2 * 56;
+        const STRTAB: u64 = DYNAMIC + 4 * 16;
+        let strings = b"\0libfoo.so.1\0";
+        let size = STRTAB + strings.len() as u64;
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1]);
+        bytes.resize(16, 0);
+        bytes.extend_from_slice(&ET_EXEC.to_le_bytes());
+        bytes.extend_from_slice(&EM_X86_64.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&(vaddr.wrapping_add(size)).to_le_bytes()); // e_entry
+        bytes.extend_from_slice(&PHDRS.to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes()); // e_shoff
+        bytes.extend_from_slice(&0u32.to_le_bytes()); // e_flags
+        for half in [64u16, 56, 2, 64, 0, 0] {
+            bytes.extend_from_slice(&half.to_le_bytes());
+        }
+
+        let segments = [
+            (PT_LOAD, 5u32, 0, vaddr, filesz),
+            (
+                PT_DYNAMIC,
+                6,
+                DYNAMIC,
+                vaddr.wrapping_add(DYNAMIC),
+                STRTAB - DYNAMIC,
+            ),
+        ];
+        for (p_type, flags, offset, vaddr, filesz) in segments {
+            bytes.extend_from_slice(&p_type.to_le_bytes());
+            bytes.extend_from_slice(&flags.to_le_bytes());
+            for field in [offset, vaddr, vaddr, filesz, filesz, 0x1000] {
+                bytes.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+
+        let strtab = vaddr.wrapping_add(STRTAB).wrapping_add(strtab_delta as u64);
+        let dyns = [
+            (DT_NEEDED, 1),
+            (DT_STRTAB, strtab),
+            (DT_STRSZ, strings.len() as u64),
+            (DT_NULL, 0),
+        ];
+        for (tag, val) in dyns {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+        bytes.extend_from_slice(strings);
+        bytes
+    }
+
+    #[test]
+    fn strtab_of_executable_at_fixed_address() {
+        let bytes = executable(0x400000, 0x1000, 0);
+        let info = process_bytes("fixed", &bytes).unwrap();
+        assert_eq!(info.elf_type, ET_EXEC);
+        assert_eq!(info.needed, ["libfoo.so.1"]);
+    }
+
+    #[test]
+    fn strtab_outside_of_any_segment() {
+        let bytes = executable(0x400000, 0x1000, 0x2000);
+        assert!(matches!(
+            process_bytes("unmapped", &bytes),
+            Err(StrtableBad(_))
+        ));
+    }
+
+    #[test]
+    fn segment_at_the_top_of_the_address_space() {
+        let bytes = executable(0xffff_ffff_ffff_ff00, 0x200, 0);
+        let info = process_bytes("top", &bytes).unwrap();
+        assert_eq!(info.needed, ["libfoo.so.1"]);
+    }
+
+    #[test]
+    fn plugin_with_entry_point() {
+        let mut bytes = executable(0, 0x1000, 0);
+        bytes[16..18].copy_from_slice(&ET_DYN.to_le_bytes());
+        let info = process_bytes("plugin", &bytes).unwrap();
+        assert!(!info.pie);
+        assert!(!info.static_pie);
+        assert_eq!(consumer_kind(&info), Kind::Plugin);
+    }
+
+    /// Directory removed again when dropped, also when the test using it fails
+    struct TempDir(PathBuf);
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn non_pie_built_by_cc() {
+        let dir =
+            TempDir(std::env::temp_dir().join(format!("so-lookup-{}-cc", std::process::id())));
+        std::fs::create_dir_all(&dir.0).unwrap();
+        std::fs::write(dir.0.join("main.c"), "int main(void) { return 0; }\n").unwrap();
+        let status = Command::new("cc")
+            .args([
+                "-no-pie",
+                "-o",
+                "main",
+                "main.c",
+                "-Wl,--no-as-needed",
+                "-lm",
+            ])
+            .current_dir(&dir.0)
+            .status();
+        let status = match status {
+            Ok(status) => status,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                eprintln!("skipped, no C compiler to build the non-PIE fixture with");
+                return;
+            }
+            Err(e) => panic!("cannot run cc: {}", e),
+        };
+        assert!(status.success());
+
+        let info = process_one(&dir.0.join("main")).unwrap();
+        assert_eq!(info.elf_type, ET_EXEC);
+        assert!(!info.pie);
+        assert!(info.needed.contains(&"libm.so.6".to_string()));
+        assert!(info.needed.contains(&"libc.so.6".to_string()));
+    }
 }
@@ -0,0 +1,177 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Everything a scan found, keyed by machine name as given by `machine_to_str`
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Report {
+    pub machines: BTreeMap<String, Machine>,
+    /// Files left out of the report as duplicates of the one they are listed under
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<PathBuf, Vec<PathBuf>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Machine {
+    /// Every DT_NEEDED entry seen, with the executables having it
+    pub sonames: BTreeMap<String, Soname>,
+    /// Dependency closure of every executable, only computed on request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub closures: BTreeMap<PathBuf, Vec<Dependency>>,
+    /// Statically linked executables, which no library update will ever reach
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub statics: BTreeMap<PathBuf, Static>,
+    /// Undefined symbols of every executable by the DT_NEEDED entry of the library providing
+    /// them, only computed on request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub imports: BTreeMap<PathBuf, BTreeMap<String, Vec<String>>>,
+    /// DT_NEEDED entries of every executable that no undefined symbol is found in, only computed
+    /// on request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unused: BTreeMap<PathBuf, Vec<String>>,
+    /// Undefined symbols of every executable that none of its DT_NEEDED entries provide, by the
+    /// DT_NEEDED entry of the indirect dependency they are found in, only computed on request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub underlinked: BTreeMap<PathBuf, BTreeMap<String, Vec<String>>>,
+    /// Undefined symbols of every executable that no library of its closure provides, as
+    /// `name@version` for versioned ones, only computed on request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub unresolved: BTreeMap<PathBuf, Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Soname {
+    pub count: usize,
+    pub consumers: Vec<Consumer>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Consumer {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub kind: Kind,
+    /// The library the entry resolves to, `None` if it cannot be found
+    pub resolved: Option<PathBuf>,
+    /// Symbol versions required from the library
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub versions: Vec<String>,
+    /// Required versions the resolved library does not define, which ld.so refuses to load the
+    /// consumer for. Weak requirements and libraries without any version definitions only get a
+    /// warning, so they are left out.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_versions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Static {
+    /// Static-pie rather than a classic static executable at a fixed address
+    pub pie: bool,
+    /// File size in bytes
+    pub size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    #[default]
+    Executable,
+    SharedLibrary,
+    /// Shared object without a DT_SONAME, meant to be loaded with dlopen
+    Plugin,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Executable => "executable",
+            Kind::SharedLibrary => "shared library",
+            Kind::Plugin => "plugin",
+        }
+    }
+}
+
+/// A library in the dependency closure of an executable
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Dependency {
+    /// DT_NEEDED entry the library was first requested by
+    pub soname: String,
+    /// `None` if it cannot be found
+    pub path: Option<PathBuf>,
+}
+
+impl Report {
+    pub fn machine(&mut self, machine: &str) -> &mut Machine {
+        self.machines.entry(machine.to_string()).or_default()
+    }
+}
+
+impl Machine {
+    pub fn add_consumer(&mut self, soname: &str, consumer: Consumer) {
+        let entry = self.sonames.entry(soname.to_string()).or_default();
+        entry.count += 1;
+        entry.consumers.push(consumer);
+    }
+
+    /// Executables with DT_NEEDED entries that cannot be found, grouped by the entry
+    pub fn missing(&self) -> BTreeMap<&str, Vec<&Path>> {
+        self.sonames
+            .iter()
+            .map(|(soname, entry)| {
+                let exes = entry
+                    .consumers
+                    .iter()
+                    .filter(|c| c.resolved.is_none())
+                    .map(|c| c.path.as_path())
+                    .collect::<Vec<_>>();
+                (soname.as_str(), exes)
+            })
+            .filter(|(_, exes)| !exes.is_empty())
+            .collect()
+    }
+
+    /// Executables with DT_NEEDED entries they do not use, grouped by the entry
+    pub fn unused_consumers(&self) -> BTreeMap<&str, Vec<&Path>> {
+        let mut consumers = BTreeMap::<_, Vec<_>>::new();
+        for (exe, unused) in &self.unused {
+            for soname in unused {
+                consumers
+                    .entry(soname.as_str())
+                    .or_default()
+                    .push(exe.as_path());
+            }
+        }
+        consumers
+    }
+
+    /// Executables requiring a symbol version from a DT_NEEDED entry, grouped by entry and version
+    pub fn version_consumers(&self) -> BTreeMap<&str, BTreeMap<&str, Vec<&Path>>> {
+        let mut consumers = BTreeMap::<_, BTreeMap<_, Vec<_>>>::new();
+        for (soname, entry) in &self.sonames {
+            for consumer in &entry.consumers {
+                for version in &consumer.versions {
+                    consumers
+                        .entry(soname.as_str())
+                        .or_default()
+                        .entry(version.as_str())
+                        .or_default()
+                        .push(consumer.path.as_path());
+                }
+            }
+        }
+        consumers
+    }
+
+    /// Executables having a library in their closure, grouped by the name it was loaded as
+    pub fn transitive_consumers(&self) -> BTreeMap<&str, Vec<&Path>> {
+        let mut consumers = BTreeMap::<_, Vec<_>>::new();
+        for (exe, closure) in &self.closures {
+            for dependency in closure.iter().filter(|d| d.path.is_some()) {
+                consumers
+                    .entry(dependency.soname.as_str())
+                    .or_default()
+                    .push(exe.as_path());
+            }
+        }
+        consumers
+    }
+}
@@ -0,0 +1,19 @@
+use goblin::container::Ctx;
+use goblin::elf::dynamic::Dynamic;
+use goblin::elf::header::Header;
+use goblin::elf::program_header::ProgramHeader;
+use goblin::elf::Elf;
+
+/// Parses just enough to find the dynamic segment, unlike `Elf::parse` which also goes through
+/// section headers, symbol tables and relocations. Kept free of anything else in the crate so that
+/// benches/parse.rs can include this file.
+pub fn parse_dynamic(
+    bytes: &[u8],
+) -> goblin::error::Result<(Header, Vec<ProgramHeader>, Option<Dynamic>)> {
+    let header = Elf::parse_header(bytes)?;
+    let ctx = Ctx::new(header.container()?, header.endianness()?);
+    let program_headers =
+        ProgramHeader::parse(bytes, header.e_phoff as usize, header.e_phnum as usize, ctx)?;
+    let dynamic = Dynamic::parse(bytes, &program_headers, ctx)?;
+    Ok((header, program_headers, dynamic))
+}
@@ -0,0 +1,304 @@
+use crate::ld_cache::LdCache;
+use crate::ld_conf;
+use crate::sysroot::Sysroot;
+use crate::ElfInfo;
+use goblin::elf::header::{
+    EI_CLASS, ELFCLASS64, EM_386, EM_AARCH64, EM_ARM, EM_PPC64, EM_RISCV, EM_S390, EM_X86_64,
+};
+use itertools::Itertools;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Debian-style multiarch directory name, which such distros add to the trusted directories
+fn multiarch_triplet(machine: u16, is_64: bool) -> Option<&'static str> {
+    Some(match (machine, is_64) {
+        (EM_386, false) => "i386-linux-gnu",
+        (EM_X86_64, true) => "x86_64-linux-gnu",
+        (EM_X86_64, false) => "x86_64-linux-gnux32",
+        (EM_ARM, false) => "arm-linux-gnueabihf",
+        (EM_AARCH64, true) => "aarch64-linux-gnu",
+        (EM_PPC64, true) => "powerpc64le-linux-gnu",
+        (EM_RISCV, true) => "riscv64-linux-gnu",
+        (EM_S390, true) => "s390x-linux-gnu",
+        _ => return None,
+    })
+}
+
+/// Possible values of $LIB; which one ld.so uses is a build-time choice of the distro, so all
+/// the usual ones are tried (files of the wrong class are skipped anyway)
+fn lib_dirs(machine: u16, is_64: bool) -> Vec<String> {
+    let mut dirs = Vec::new();
+    if let Some(triplet) = multiarch_triplet(machine, is_64) {
+        dirs.push(format!("lib/{}", triplet));
+    }
+    dirs.push(if is_64 { "lib64" } else { "lib32" }.to_string());
+    dirs.push("lib".to_string());
+    dirs
+}
+
+/// AT_PLATFORM as reported by the kernel for processes of this architecture
+fn platform(machine: u16, is_64: bool) -> Option<&'static str> {
+    Some(match (machine, is_64) {
+        (EM_386, false) => "i686",
+        (EM_X86_64, _) => "x86_64",
+        (EM_ARM, false) => "v7l",
+        (EM_AARCH64, true) => "aarch64",
+        (EM_PPC64, true) => "ppc64le",
+        (EM_RISCV, true) => "riscv64",
+        (EM_S390, true) => "s390x",
+        _ => return None,
+    })
+}
+
+/// The trusted directories glibc is built with
+fn default_dirs(machine: u16, is_64: bool) -> Vec<PathBuf> {
+    lib_dirs(machine, is_64)
+        .into_iter()
+        .flat_map(|lib| [Path::new("/").join(&lib), Path::new("/usr").join(&lib)])
+        .collect()
+}
+
+/// Expands $ORIGIN, $LIB and $PLATFORM (also in the ${NAME} form) in an RPATH or RUNPATH entry.
+/// As $LIB is ambiguous, one entry may expand to several directories. Entries with unknown tokens
+/// are dropped, same as ld.so does.
+fn expand_tokens(entry: &str, object: &ElfInfo, origin: &Path) -> Vec<PathBuf> {
+    let mut expansions = vec![String::new()];
+    let mut rest = entry;
+    while let Some(pos) = rest.find('$') {
+        for expansion in &mut expansions {
+            expansion.push_str(&rest[..pos]);
+        }
+        rest = &rest[pos + 1..];
+
+        let (name, tail) = if let Some(braced) = rest.strip_prefix('{') {
+            match braced.split_once('}') {
+                Some(split) => split,
+                None => return Vec::new(),
+            }
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            rest.split_at(end)
+        };
+        let values = match name {
+            "ORIGIN" => vec![origin.to_string_lossy().into_owned()],
+            "LIB" => lib_dirs(object.machine, object.is_64),
+            "PLATFORM" => match platform(object.machine, object.is_64) {
+                Some(platform) => vec![platform.to_string()],
+                None => return Vec::new(),
+            },
+            _ => return Vec::new(),
+        };
+        expansions = expansions
+            .iter()
+            .cartesian_product(&values)
+            .map(|(expansion, value)| format!("{}{}", expansion, value))
+            .collect();
+        rest = tail;
+    }
+    for expansion in &mut expansions {
+        expansion.push_str(rest);
+    }
+
+    expansions.into_iter().map(PathBuf::from).collect()
+}
+
+/// Finds the file the dynamic loader would map for a DT_NEEDED entry. The search paths and the
+/// results are in-root paths of the sysroot, `open_path` gives the host path to read a result from.
+pub struct Resolver {
+    sysroot: Sysroot,
+    ld_library_path: Vec<PathBuf>,
+    cache: Option<LdCache>,
+    conf_dirs: Vec<PathBuf>,
+    /// (e_machine, is_64) of every candidate file looked at so far
+    headers: RefCell<HashMap<PathBuf, Option<(u16, bool)>>>,
+}
+
+impl Resolver {
+    pub fn new(sysroot: Sysroot, ld_library_path: Vec<PathBuf>) -> Self {
+        let cache = LdCache::load(&sysroot.open_path(Path::new("/etc/ld.so.cache")));
+        let conf_dirs = ld_conf::parse(&sysroot, Path::new("/etc/ld.so.conf"));
+        Self {
+            sysroot,
+            ld_library_path,
+            cache,
+            conf_dirs,
+            headers: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// In-root path of a host path, unchanged if it is outside of the sysroot
+    pub fn in_root(&self, path: &Path) -> PathBuf {
+        self.sysroot
+            .in_root(path)
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    /// In-root directory $ORIGIN expands to for the main program at the given host path. ld.so
+    /// takes it from /proc/self/exe, so symlinks are resolved.
+    pub fn program_origin(&self, path: &Path) -> PathBuf {
+        let path = self.sysroot.canonicalize(&self.in_root(path));
+        path.parent().map(Path::to_path_buf).unwrap_or_default()
+    }
+
+    /// In-root directory $ORIGIN expands to for a library at the given resolved path. Unlike for
+    /// the main program, this is just the directory it was found in.
+    pub fn library_origin(&self, path: &Path) -> PathBuf {
+        path.parent().map(Path::to_path_buf).unwrap_or_default()
+    }
+
+    /// Host path to read a resolved library or PT_INTERP from, following symlinks inside the
+    /// sysroot
+    pub fn open_path(&self, path: &Path) -> PathBuf {
+        self.sysroot.open_path(path)
+    }
+
+    /// Looks `soname` up in the same order as ld.so: DT_RPATH (ignored when DT_RUNPATH is present),
+    /// LD_LIBRARY_PATH, DT_RUNPATH and finally ld.so.cache and the default directories (unless
+    /// DF_1_NODEFLIB is set). Directories from ld.so.conf are tried right after the cache, as
+    /// unpacked root filesystems often come without an up-to-date one.
+    ///
+    /// `chain` lists the object with the DT_NEEDED entry and its origin first, followed by the
+    /// object that loaded it and so on up to the main program: when the first one has no
+    /// DT_RUNPATH, the DT_RPATH of all of them is searched.
+    pub fn resolve(&self, soname: &str, chain: &[(&ElfInfo, &Path)]) -> Option<PathBuf> {
+        let (object, origin) = chain[0];
+        if soname.contains('/') {
+            let path = PathBuf::from(soname);
+            return self.is_compatible(&path, object).then_some(path);
+        }
+
+        let rpath_chain = if object.runpath.is_empty() {
+            chain
+        } else {
+            &[]
+        };
+        let search_path = rpath_chain
+            .iter()
+            .filter(|(loader, _)| loader.runpath.is_empty())
+            .flat_map(|&(loader, origin)| {
+                loader
+                    .rpath
+                    .iter()
+                    .flat_map(move |entry| expand_tokens(entry, loader, origin))
+            })
+            .chain(self.ld_library_path.iter().cloned())
+            .chain(
+                object
+                    .runpath
+                    .iter()
+                    .flat_map(|entry| expand_tokens(entry, object, origin)),
+            )
+            .map(|dir| dir.join(soname));
+        if let Some(found) = search_path
+            .into_iter()
+            .find(|candidate| self.is_compatible(candidate, object))
+        {
+            return Some(found);
+        }
+
+        if object.nodeflib {
+            return None;
+        }
+        self.cache
+            .as_ref()
+            .and_then(|cache| cache.lookup(soname, object.machine, object.is_64))
+            .map(Path::to_path_buf)
+            .into_iter()
+            .chain(self.conf_dirs.iter().map(|dir| dir.join(soname)))
+            .chain(
+                default_dirs(object.machine, object.is_64)
+                    .into_iter()
+                    .map(|dir| dir.join(soname)),
+            )
+            .find(|candidate| self.is_compatible(candidate, object))
+    }
+
+    /// ld.so silently skips files of the wrong class or architecture and keeps searching
+    fn is_compatible(&self, candidate: &Path, object: &ElfInfo) -> bool {
+        let mut headers = self.headers.borrow_mut();
+        let header = headers
+            .entry(candidate.to_path_buf())
+            .or_insert_with(|| read_header(&self.sysroot.open_path(candidate)));
+
+        *header == Some((object.machine, object.is_64))
+    }
+}
+
+fn read_header(path: &Path) -> Option<(u16, bool)> {
+    let mut bytes = [0; 64];
+    let mut file = File::open(path).ok()?;
+    file.read_exact(&mut bytes).ok()?;
+    let header = goblin::elf::Elf::parse_header(&bytes).ok()?;
+
+    Some((header.e_machine, header.e_ident[EI_CLASS] == ELFCLASS64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn object() -> ElfInfo {
+        ElfInfo {
+            machine: EM_X86_64,
+            soname: None,
+            interpreter: None,
+            is_64: true,
+            needed: Vec::new(),
+            rpath: Vec::new(),
+            runpath: Vec::new(),
+            nodeflib: false,
+            elf_type: goblin::elf::header::ET_DYN,
+            pie: false,
+            static_pie: false,
+            version_needs: BTreeMap::new(),
+            version_defs: Vec::new(),
+        }
+    }
+
+    fn expand(entry: &str) -> Vec<PathBuf> {
+        expand_tokens(entry, &object(), Path::new("/opt/app/bin"))
+    }
+
+    #[test]
+    fn entry_without_tokens() {
+        assert_eq!(expand("/usr/local/lib"), [Path::new("/usr/local/lib")]);
+    }
+
+    #[test]
+    fn braced_and_bare_tokens() {
+        let expected = [Path::new("/opt/app/bin/../lib")];
+        assert_eq!(expand("$ORIGIN/../lib"), expected);
+        assert_eq!(expand("${ORIGIN}/../lib"), expected);
+        assert_eq!(expand("$PLATFORM"), [Path::new("x86_64")]);
+    }
+
+    #[test]
+    fn unknown_token_drops_the_entry() {
+        assert!(expand("$FOO/lib").is_empty());
+        assert!(expand("$ORIGINAL/lib").is_empty());
+        assert!(expand("${ORIGIN}/${FOO}").is_empty());
+    }
+
+    #[test]
+    fn unterminated_brace_drops_the_entry() {
+        assert!(expand("${ORIGIN/lib").is_empty());
+    }
+
+    #[test]
+    fn lib_expands_to_several_directories() {
+        assert_eq!(
+            expand("$ORIGIN/../$LIB/$PLATFORM"),
+            [
+                Path::new("/opt/app/bin/../lib/x86_64-linux-gnu/x86_64"),
+                Path::new("/opt/app/bin/../lib64/x86_64"),
+                Path::new("/opt/app/bin/../lib/x86_64"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::model::{Kind, Report};
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const SCHEMA: &str = "
+CREATE TABLE machines (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+-- both the scanned executables and the libraries their dependencies resolve to
+CREATE TABLE files (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL UNIQUE,
+    machine_id INTEGER NOT NULL REFERENCES machines(id),
+    -- 'executable', 'shared library' or 'plugin' for files that were scanned themselves
+    kind TEXT
+);
+-- sonames of different machines refer to different libraries
+CREATE TABLE sonames (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    machine_id INTEGER NOT NULL REFERENCES machines(id),
+    UNIQUE (name, machine_id)
+);
+-- DT_NEEDED entries; resolved_id is NULL when the library cannot be found
+CREATE TABLE needed (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    soname_id INTEGER NOT NULL REFERENCES sonames(id),
+    resolved_id INTEGER REFERENCES files(id),
+    PRIMARY KEY (file_id, soname_id)
+);
+-- symbol versions required through DT_NEEDED entries, missing when the resolved library has
+-- version definitions but not this one
+CREATE TABLE versions (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    soname_id INTEGER NOT NULL REFERENCES sonames(id),
+    version TEXT NOT NULL,
+    missing INTEGER NOT NULL,
+    PRIMARY KEY (file_id, soname_id, version)
+);
+-- transitive dependencies, in load order, when computed
+CREATE TABLE closure (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    position INTEGER NOT NULL,
+    soname_id INTEGER NOT NULL REFERENCES sonames(id),
+    resolved_id INTEGER REFERENCES files(id),
+    PRIMARY KEY (file_id, position)
+);
+-- statically linked executables, which have no rows in needed
+CREATE TABLE statics (
+    file_id INTEGER PRIMARY KEY REFERENCES files(id),
+    pie INTEGER NOT NULL,
+    size INTEGER NOT NULL
+);
+-- duplicates of a scanned file, left out of the other tables
+CREATE TABLE aliases (
+    path TEXT NOT NULL,
+    alias TEXT NOT NULL UNIQUE
+);
+-- undefined symbols, with the DT_NEEDED entry of the library they are found in
+CREATE TABLE imports (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    soname_id INTEGER NOT NULL REFERENCES sonames(id),
+    symbol TEXT NOT NULL
+);
+-- the part of imports found in indirect dependencies only
+CREATE TABLE underlinked (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    soname_id INTEGER NOT NULL REFERENCES sonames(id),
+    symbol TEXT NOT NULL
+);
+-- undefined symbols no library in the closure provides, as name@version when versioned
+CREATE TABLE unresolved (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    symbol TEXT NOT NULL,
+    PRIMARY KEY (file_id, symbol)
+);
+CREATE INDEX imports_file ON imports(file_id);
+CREATE INDEX imports_symbol ON imports(symbol);
+-- DT_NEEDED entries none of the undefined symbols of the file are found in
+CREATE TABLE unused (
+    file_id INTEGER NOT NULL REFERENCES files(id),
+    soname_id INTEGER NOT NULL REFERENCES sonames(id),
+    PRIMARY KEY (file_id, soname_id)
+);
+CREATE INDEX files_machine ON files(machine_id);
+CREATE INDEX needed_soname ON needed(soname_id);
+CREATE INDEX needed_resolved ON needed(resolved_id);
+CREATE INDEX closure_soname ON closure(soname_id);
+CREATE INDEX closure_resolved ON closure(resolved_id);
+
+CREATE VIEW needed_paths AS
+SELECT machines.name AS machine, files.path AS path, sonames.name AS soname,
+       resolved.path AS resolved
+FROM needed
+JOIN files ON files.id = needed.file_id
+JOIN sonames ON sonames.id = needed.soname_id
+JOIN machines ON machines.id = sonames.machine_id
+LEFT JOIN files AS resolved ON resolved.id = needed.resolved_id;
+";
+
+fn file_id(tx: &Transaction, path: &Path, machine_id: i64) -> rusqlite::Result<i64> {
+    let path = path.to_string_lossy();
+    if let Some(id) = tx
+        .prepare_cached("SELECT id FROM files WHERE path = ?")?
+        .query_row([&path], |row| row.get(0))
+        .optional()?
+    {
+        return Ok(id);
+    }
+    tx.prepare_cached("INSERT INTO files (path, machine_id) VALUES (?, ?)")?
+        .execute(params![path, machine_id])?;
+    Ok(tx.last_insert_rowid())
+}
+
+fn soname_id(tx: &Transaction, soname: &str, machine_id: i64) -> rusqlite::Result<i64> {
+    if let Some(id) = tx
+        .prepare_cached("SELECT id FROM sonames WHERE name = ? AND machine_id = ?")?
+        .query_row(params![soname, machine_id], |row| row.get(0))
+        .optional()?
+    {
+        return Ok(id);
+    }
+    tx.prepare_cached("INSERT INTO sonames (name, machine_id) VALUES (?, ?)")?
+        .execute(params![soname, machine_id])?;
+    Ok(tx.last_insert_rowid())
+}
+
+/// Fills `table`, which has the columns of the imports table
+fn insert_imports(
+    tx: &Transaction,
+    table: &str,
+    imports: &BTreeMap<PathBuf, BTreeMap<String, Vec<String>>>,
+    machine_id: i64,
+) -> rusqlite::Result<()> {
+    let insert = format!(
+        "INSERT INTO {} (file_id, soname_id, symbol) VALUES (?, ?, ?)",
+        table
+    );
+    for (exe, imports) in imports {
+        let exe_id = file_id(tx, exe, machine_id)?;
+        for (soname, symbols) in imports {
+            let soname_id = soname_id(tx, soname, machine_id)?;
+            for symbol in symbols {
+                tx.prepare_cached(&insert)?
+                    .execute(params![exe_id, soname_id, symbol])?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the report into a new SQLite database at `path`, replacing any existing file
+pub fn write(report: &Report, path: &Path) -> rusqlite::Result<()> {
+    // if this fails, creating the schema below will
+    let _ = std::fs::remove_file(path);
+    let mut connection = Connection::open(path)?;
+    let tx = connection.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+
+    for (machine, entry) in &report.machines {
+        tx.execute("INSERT INTO machines (name) VALUES (?)", [machine])?;
+        let machine_id = tx.last_insert_rowid();
+
+        for (soname, entry) in &entry.sonames {
+            let soname_id = soname_id(&tx, soname, machine_id)?;
+            for consumer in &entry.consumers {
+                let consumer_id = file_id(&tx, &consumer.path, machine_id)?;
+                tx.prepare_cached("UPDATE files SET kind = ? WHERE id = ?")?
+                    .execute(params![consumer.kind.name(), consumer_id])?;
+                let resolved_id = match &consumer.resolved {
+                    Some(resolved) => Some(file_id(&tx, resolved, machine_id)?),
+                    None => None,
+                };
+                tx.prepare_cached(
+                    "INSERT OR IGNORE INTO needed (file_id, soname_id, resolved_id) VALUES (?, ?, ?)",
+                )?
+                .execute(params![consumer_id, soname_id, resolved_id])?;
+                for version in &consumer.versions {
+                    let missing = consumer.missing_versions.contains(version);
+                    tx.prepare_cached(
+                        "INSERT OR IGNORE INTO versions (file_id, soname_id, version, missing) VALUES (?, ?, ?, ?)",
+                    )?
+                    .execute(params![consumer_id, soname_id, version, missing])?;
+                }
+            }
+        }
+
+        for (exe, closure) in &entry.closures {
+            let exe_id = file_id(&tx, exe, machine_id)?;
+            for (position, dependency) in closure.iter().enumerate() {
+                let soname_id = soname_id(&tx, &dependency.soname, machine_id)?;
+                let resolved_id = match &dependency.path {
+                    Some(resolved) => Some(file_id(&tx, resolved, machine_id)?),
+                    None => None,
+                };
+                tx.prepare_cached(
+                    "INSERT INTO closure (file_id, position, soname_id, resolved_id) VALUES (?, ?, ?, ?)",
+                )?
+                .execute(params![exe_id, position, soname_id, resolved_id])?;
+            }
+        }
+
+        insert_imports(&tx, "imports", &entry.imports, machine_id)?;
+        insert_imports(&tx, "underlinked", &entry.underlinked, machine_id)?;
+
+        for (exe, unused) in &entry.unused {
+            let exe_id = file_id(&tx, exe, machine_id)?;
+            for soname in unused {
+                let soname_id = soname_id(&tx, soname, machine_id)?;
+                tx.prepare_cached(
+                    "INSERT OR IGNORE INTO unused (file_id, soname_id) VALUES (?, ?)",
+                )?
+                .execute(params![exe_id, soname_id])?;
+            }
+        }
+
+        for (exe, unresolved) in &entry.unresolved {
+            let exe_id = file_id(&tx, exe, machine_id)?;
+            for symbol in unresolved {
+                tx.prepare_cached("INSERT INTO unresolved (file_id, symbol) VALUES (?, ?)")?
+                    .execute(params![exe_id, symbol])?;
+            }
+        }
+
+        for (exe, entry) in &entry.statics {
+            let exe_id = file_id(&tx, exe, machine_id)?;
+            tx.prepare_cached("UPDATE files SET kind = ? WHERE id = ?")?
+                .execute(params![Kind::Executable.name(), exe_id])?;
+            tx.prepare_cached("INSERT INTO statics (file_id, pie, size) VALUES (?, ?, ?)")?
+                .execute(params![exe_id, entry.pie, entry.size])?;
+        }
+    }
+
+    for (path, aliases) in &report.aliases {
+        for alias in aliases {
+            tx.prepare_cached("INSERT INTO aliases (path, alias) VALUES (?, ?)")?
+                .execute(params![path.to_string_lossy(), alias.to_string_lossy()])?;
+        }
+    }
+
+    tx.commit()
+}
@@ -0,0 +1,316 @@
+use crate::closure::Loader;
+use crate::model::Dependency;
+use crate::parse::parse_dynamic;
+use crate::versions;
+use crate::ElfInfo;
+use crate::ErrorKind::{self, CannotRead, MissingDynTag, NotAnElf};
+use crate::{dynamic_strtab, dynamic_tag, vaddr_to_offset};
+use glob::Pattern;
+use goblin::container::Ctx;
+use goblin::elf::dynamic::{Dynamic, DT_GNU_HASH, DT_HASH, DT_SYMTAB};
+use goblin::elf::header::EM_S390;
+use goblin::elf::program_header::ProgramHeader;
+use goblin::elf::section_header::SHN_UNDEF;
+use goblin::elf::sym::{
+    Symtab, STB_GLOBAL, STB_GNU_UNIQUE, STB_WEAK, STT_OBJECT, STV_DEFAULT, STV_PROTECTED,
+};
+use memmap2::Mmap;
+use scroll::Pread;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub name: String,
+    /// Weak references may stay unresolved, the symbol then has address 0
+    pub weak: bool,
+    /// Version required through .gnu.version_r, `None` for unversioned references
+    pub version: Option<String>,
+}
+
+impl Import {
+    /// `name@version` as written by readelf, or just the name
+    pub fn display_name(&self) -> String {
+        match &self.version {
+            Some(version) => format!("{}@{}", self.name, version),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// A definition of an exported symbol
+#[derive(Debug)]
+pub struct Export {
+    /// Version from .gnu.version_d, `None` for unversioned symbols and those of the base version
+    pub version: Option<String>,
+    /// Only there for references to that version, not the default one of the symbol
+    pub hidden: bool,
+}
+
+/// The dynamic symbols an object takes from and offers to the others
+#[derive(Debug, Default)]
+pub struct Symbols {
+    pub imports: Vec<Import>,
+    /// Definitions by symbol name, as a symbol may have one for each of its versions
+    pub exports: HashMap<String, Vec<Export>>,
+    /// Defined data objects. In executables these are mostly copies of library variables made by
+    /// copy relocations, so they come from a library as much as the undefined symbols do.
+    pub objects: Vec<Import>,
+}
+
+impl Symbols {
+    /// Whether `import` binds to one of our definitions, following the rules of ld.so: a
+    /// versioned reference wants that version or an unversioned definition, an unversioned one
+    /// takes the default version, and hidden versions are only ever found by name.
+    pub fn provides(&self, import: &Import) -> bool {
+        let exports = match self.exports.get(&import.name) {
+            Some(exports) => exports,
+            None => return false,
+        };
+        exports
+            .iter()
+            .any(|export| match (&import.version, &export.version) {
+                (Some(wanted), Some(version)) => wanted == version,
+                _ => !export.hidden,
+            })
+    }
+}
+
+/// Number of dynamic symbols, which only the hash tables tell: DT_HASH has a chain for every
+/// symbol, DT_GNU_HASH leaves out the first few and ends the chain of the last one with its lowest
+/// bit set. `None` if the file has neither or they are cut short.
+fn symbol_count(
+    bytes: &[u8],
+    program_headers: &[ProgramHeader],
+    dynamic: &Dynamic,
+    machine: u16,
+    ctx: Ctx,
+) -> Option<usize> {
+    let word = |offset: usize| {
+        bytes
+            .pread_with::<u32>(offset, ctx.le)
+            .ok()
+            .map(|word| word as usize)
+    };
+    let offset = |tag| vaddr_to_offset(program_headers, dynamic_tag(dynamic, tag)?);
+
+    if let Some(hash) = offset(DT_HASH) {
+        let nchain = (hash as usize).checked_add(4)?;
+        // 64-bit s390 is alone in using 8-byte hash table entries
+        if machine == EM_S390 && ctx.container.is_big() {
+            return bytes
+                .pread_with::<u64>(nchain, ctx.le)
+                .ok()
+                .map(|nchain| nchain as usize);
+        }
+        return word(nchain);
+    }
+
+    let hash = offset(DT_GNU_HASH)? as usize;
+    let buckets = word(hash)?;
+    let first = word(hash.checked_add(4)?)?;
+    let bloom_size = word(hash.checked_add(8)?)?;
+    let bloom_word = if ctx.container.is_big() { 8 } else { 4 };
+    let buckets_offset = hash
+        .checked_add(16)?
+        .checked_add(bloom_size.checked_mul(bloom_word)?)?;
+    let chains_offset = buckets_offset.checked_add(buckets.checked_mul(4)?)?;
+    let last = (0..buckets).try_fold(0, |last, bucket| {
+        Some(word(buckets_offset + bucket * 4)?.max(last))
+    })?;
+    if last < first {
+        return Some(first);
+    }
+    let mut index = last;
+    loop {
+        let chain = word(chains_offset.checked_add((index - first).checked_mul(4)?)?)?;
+        index += 1;
+        if chain & 1 != 0 {
+            return Some(index);
+        }
+    }
+}
+
+/// Reads the dynamic symbol table, `None` if there is none. Like the rest of the dynamic linking
+/// information it is found through the dynamic segment, section headers may well be stripped.
+pub fn read(path: &Path) -> Result<Option<Symbols>, ErrorKind> {
+    let file = File::open(path).map_err(CannotRead)?;
+    let file = unsafe { Mmap::map(&file) }.map_err(CannotRead)?;
+    let (header, program_headers, dynamic) = parse_dynamic(&file).map_err(NotAnElf)?;
+    let dynamic = match dynamic {
+        Some(dynamic) => dynamic,
+        None => return Ok(None),
+    };
+    let ctx = Ctx::new(
+        header.container().map_err(NotAnElf)?,
+        header.endianness().map_err(NotAnElf)?,
+    );
+    let table = dynamic_strtab(&file, &program_headers, &dynamic)?;
+    let symtab = match dynamic_tag(&dynamic, DT_SYMTAB) {
+        Some(symtab) => vaddr_to_offset(&program_headers, symtab).ok_or_else(|| {
+            NotAnElf(goblin::error::Error::Malformed(format!(
+                "DT_SYMTAB {:#x} is not mapped by any PT_LOAD segment",
+                symtab
+            )))
+        })?,
+        None => return Ok(None),
+    };
+    let count = symbol_count(&file, &program_headers, &dynamic, header.e_machine, ctx)
+        .ok_or(MissingDynTag(DT_GNU_HASH))?;
+    let table_symbols = Symtab::parse(&file, symtab as usize, count, ctx).map_err(NotAnElf)?;
+
+    let mut symbols = Symbols::default();
+    // symbols are left unversioned without the version tables
+    let versions = versions::read(&file, &program_headers, &dynamic, &table, ctx);
+    let versyms = versions::versym(&file, &program_headers, &dynamic, count, ctx);
+
+    for (index, sym) in table_symbols.iter().enumerate() {
+        let name = match table.get_at(sym.st_name) {
+            Some(name) if !name.is_empty() => name,
+            _ => continue,
+        };
+        let bind = sym.st_bind();
+        if bind != STB_GLOBAL && bind != STB_WEAK && bind != STB_GNU_UNIQUE {
+            continue;
+        }
+        let versym = versyms.as_ref().and_then(|versyms| versyms.get_at(index));
+        let version = versym
+            .as_ref()
+            .and_then(|versym| versions.names.get(&versym.version()))
+            .cloned();
+        let hidden = versym.is_some_and(|versym| versym.is_hidden());
+        if sym.st_shndx == SHN_UNDEF as usize {
+            symbols.imports.push(Import {
+                name: name.to_string(),
+                weak: bind == STB_WEAK,
+                version,
+            });
+        } else if matches!(sym.st_visibility(), STV_DEFAULT | STV_PROTECTED) {
+            if sym.st_type() == STT_OBJECT {
+                symbols.objects.push(Import {
+                    name: name.to_string(),
+                    weak: false,
+                    version: version.clone(),
+                });
+            }
+            symbols
+                .exports
+                .entry(name.to_string())
+                .or_default()
+                .push(Export { version, hidden });
+        }
+    }
+    Ok(Some(symbols))
+}
+
+/// Undefined symbols of the executable at `path` grouped by the DT_NEEDED entry of the library
+/// providing them, `None` if its symbols cannot be read. Like ld.so's global lookup scope,
+/// libraries are searched in load order, so the provider is not necessarily a direct dependency.
+pub fn imports(
+    loader: &mut Loader,
+    path: &Path,
+    closure: &[Dependency],
+) -> Option<BTreeMap<String, Vec<String>>> {
+    let symbols = read(path).ok()??;
+    let mut imports = BTreeMap::<_, Vec<_>>::new();
+    let scope = closure
+        .iter()
+        .filter_map(|d| Some((&d.soname, loader.symbols(d.path.as_ref()?)?)))
+        .collect::<Vec<_>>();
+
+    for import in symbols.imports.iter().chain(&symbols.objects) {
+        if let Some((soname, _)) = scope.iter().find(|(_, symbols)| symbols.provides(import)) {
+            imports
+                .entry(soname.to_string())
+                .or_default()
+                .push(import.name.clone());
+        }
+    }
+    for symbols in imports.values_mut() {
+        symbols.sort();
+        symbols.dedup();
+    }
+    Some(imports)
+}
+
+/// Undefined symbols of the executable at `path` that no library of its closure provides, which
+/// ld.so refuses to load it for. Weak references are allowed to stay unresolved. `None` if the
+/// symbols of the executable or of some library of its closure cannot be read, which leaves no
+/// way to tell.
+pub fn unresolved(loader: &mut Loader, path: &Path, closure: &[Dependency]) -> Option<Vec<String>> {
+    let symbols = read(path).ok()??;
+    let scope = closure
+        .iter()
+        .map(|d| loader.symbols(d.path.as_ref()?))
+        .collect::<Option<Vec<_>>>()?;
+    let mut unresolved = symbols
+        .imports
+        .iter()
+        .filter(|import| !import.weak)
+        .filter(|import| !scope.iter().any(|symbols| symbols.provides(import)))
+        .map(Import::display_name)
+        .collect::<Vec<_>>();
+    unresolved.sort();
+    unresolved.dedup();
+    Some(unresolved)
+}
+
+/// DT_NEEDED entries of an executable none of its `imports` are found in. Libraries whose symbols
+/// cannot be read are given the benefit of the doubt, and so are those matching `allowed`, meant
+/// for libraries doing their work from initializers. The program interpreter is loaded anyway, so
+/// depending on it costs nothing.
+pub fn unused(
+    loader: &mut Loader,
+    executable: &ElfInfo,
+    closure: &[Dependency],
+    imports: &BTreeMap<String, Vec<String>>,
+    allowed: &[Pattern],
+) -> Vec<String> {
+    executable
+        .needed
+        .iter()
+        .filter(|soname| !imports.contains_key(*soname))
+        .filter(|soname| !allowed.iter().any(|pattern| pattern.matches(soname)))
+        .filter(|soname| {
+            let interpreter = executable.interpreter.as_deref().map(Path::new);
+            interpreter.and_then(Path::file_name) != Some(OsStr::new(soname))
+        })
+        .filter(|soname| {
+            closure
+                .iter()
+                .find(|d| &d.soname == *soname)
+                .and_then(|d| d.path.as_ref())
+                .is_some_and(|path| loader.symbols(path).is_some())
+        })
+        .cloned()
+        .collect()
+}
+
+/// The part of `imports` found in indirect dependencies only, which a link with --as-needed would
+/// not see. Direct dependencies are loaded before any indirect one, so a symbol attributed to an
+/// indirect dependency is not exported by any direct one.
+pub fn underlinked(
+    executable: &ElfInfo,
+    imports: &BTreeMap<String, Vec<String>>,
+) -> BTreeMap<String, Vec<String>> {
+    imports
+        .iter()
+        .filter(|(soname, _)| !executable.needed.contains(soname))
+        .map(|(soname, symbols)| (soname.clone(), symbols.clone()))
+        .collect()
+}
+
+/// Reads soname patterns, one per line, ignoring empty lines and `#` comments
+pub fn read_allowlist(path: &Path) -> std::io::Result<Vec<Pattern>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            Pattern::new(line)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.msg))
+        })
+        .collect()
+}
@@ -0,0 +1,81 @@
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+
+/// Gives up on symlink chains longer than this, like the kernel does (ELOOP)
+const MAX_SYMLINKS: usize = 40;
+
+/// Root directory of the filesystem being analyzed. Paths inside it ("in-root" paths) are what
+/// the analyzed binaries see, host paths are what we can actually open.
+#[derive(Debug)]
+pub struct Sysroot {
+    root: PathBuf,
+}
+
+impl Sysroot {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.root == Path::new("/")
+    }
+
+    /// Host path of an in-root path, without following any symlinks
+    pub fn host(&self, path: &Path) -> PathBuf {
+        self.root.join(path.strip_prefix("/").unwrap_or(path))
+    }
+
+    /// In-root path of a host path, if it is inside the sysroot at all
+    pub fn in_root(&self, path: &Path) -> Option<PathBuf> {
+        path.strip_prefix(&self.root)
+            .ok()
+            .map(|path| Path::new("/").join(path))
+    }
+
+    /// Resolves symlinks of an in-root path, treating absolute link targets as relative to the
+    /// sysroot instead of the host
+    pub fn canonicalize(&self, path: &Path) -> PathBuf {
+        if self.is_host() {
+            return path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        }
+
+        let mut resolved = PathBuf::from("/");
+        let mut pending = path
+            .components()
+            .rev()
+            .map(|c| c.as_os_str().to_owned())
+            .collect::<Vec<OsString>>();
+        let mut links = 0;
+        while let Some(component) = pending.pop() {
+            match Path::new(&component).components().next() {
+                Some(Component::RootDir) => resolved = PathBuf::from("/"),
+                Some(Component::ParentDir) => {
+                    resolved.pop();
+                }
+                Some(Component::Normal(name)) => {
+                    let candidate = resolved.join(name);
+                    match std::fs::read_link(self.host(&candidate)) {
+                        Ok(target) if links < MAX_SYMLINKS => {
+                            links += 1;
+                            pending.extend(
+                                target.components().rev().map(|c| c.as_os_str().to_owned()),
+                            );
+                        }
+                        _ => resolved = candidate,
+                    }
+                }
+                _ => {}
+            }
+        }
+        resolved
+    }
+
+    /// Host path that can be opened to read the file an in-root path refers to
+    pub fn open_path(&self, path: &Path) -> PathBuf {
+        if self.is_host() {
+            path.to_path_buf()
+        } else {
+            self.host(&self.canonicalize(path))
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::model::{Kind, Report};
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Writes the plain text reports, a few files per machine, into `dir`
+pub fn write(report: &Report, dir: &Path) -> std::io::Result<()> {
+    for (machine, entry) in &report.machines {
+        let mut output = File::create(dir.join(format!("m_{}.txt", machine)))?;
+        for (soname, entry) in entry
+            .sonames
+            .iter()
+            .sorted_by_key(|(_, entry)| entry.count as isize)
+            .rev()
+        {
+            writeln!(output, "{} ({} exes)", soname, entry.count)?;
+
+            let resolutions = entry.consumers.iter().into_group_map_by(|c| &c.resolved);
+            for (resolved, exes) in resolutions
+                .into_iter()
+                .sorted_by_key(|(resolved, _)| *resolved)
+                .sorted_by_key(|(_, exes)| exes.len() as isize)
+                .rev()
+            {
+                match resolved {
+                    Some(resolved) => writeln!(
+                        output,
+                        "    => {} ({} exes)",
+                        resolved.display(),
+                        exes.len()
+                    )?,
+                    None => writeln!(output, "    => not found ({} exes)", exes.len())?,
+                }
+                for consumer in exes.iter().sorted_by_key(|c| &c.path) {
+                    write!(output, "        <= {}", consumer.path.display())?;
+                    let mut notes = Vec::new();
+                    if consumer.kind != Kind::Executable {
+                        notes.push(consumer.kind.name().to_string());
+                    }
+                    if let Some(aliases) = report.aliases.get(&consumer.path) {
+                        notes.push(format!("{} aliases", aliases.len()));
+                    }
+                    if !consumer.missing_versions.is_empty() {
+                        notes.push(format!("missing {}", consumer.missing_versions.join(" ")));
+                    }
+                    if !notes.is_empty() {
+                        write!(output, " ({})", notes.join(", "))?;
+                    }
+                    writeln!(output)?;
+                }
+            }
+        }
+
+        let missing = entry.missing();
+        if !missing.is_empty() {
+            let output = File::create(dir.join(format!("missing_{}.txt", machine)))?;
+            write_consumers(output, missing.into_iter())?;
+        }
+
+        let unused = entry.unused_consumers();
+        if !unused.is_empty() {
+            let output = File::create(dir.join(format!("unused_{}.txt", machine)))?;
+            write_consumers(output, unused.into_iter())?;
+        }
+
+        let versions = entry.version_consumers();
+        if !versions.is_empty() {
+            let mut output = File::create(dir.join(format!("versions_{}.txt", machine)))?;
+            for (soname, versions) in versions {
+                writeln!(output, "{}", soname)?;
+                for (version, exes) in versions {
+                    writeln!(output, "    {} ({} exes)", version, exes.len())?;
+                    for exe in exes.into_iter().sorted() {
+                        writeln!(output, "        <= {}", exe.display())?;
+                    }
+                }
+            }
+        }
+
+        if !entry.closures.is_empty() {
+            let mut output = File::create(dir.join(format!("closure_{}.txt", machine)))?;
+            for (exe, closure) in &entry.closures {
+                writeln!(output, "{} ({} libs)", exe.display(), closure.len())?;
+                for dependency in closure {
+                    match &dependency.path {
+                        Some(path) => writeln!(
+                            output,
+                            "        {} => {}",
+                            dependency.soname,
+                            path.display()
+                        )?,
+                        None => writeln!(output, "        {} => not found", dependency.soname)?,
+                    }
+                }
+            }
+
+            let output = File::create(dir.join(format!("transitive_{}.txt", machine)))?;
+            write_consumers(output, entry.transitive_consumers().into_iter())?;
+        }
+
+        if !entry.imports.is_empty() {
+            let output = File::create(dir.join(format!("symbols_{}.txt", machine)))?;
+            write_imports(output, &entry.imports)?;
+        }
+
+        if !entry.underlinked.is_empty() {
+            let output = File::create(dir.join(format!("underlinked_{}.txt", machine)))?;
+            write_imports(output, &entry.underlinked)?;
+        }
+
+        if !entry.unresolved.is_empty() {
+            let mut output = File::create(dir.join(format!("unresolved_{}.txt", machine)))?;
+            for (exe, symbols) in &entry.unresolved {
+                writeln!(output, "{} ({} symbols)", exe.display(), symbols.len())?;
+                for symbol in symbols {
+                    writeln!(output, "        {}", symbol)?;
+                }
+            }
+        }
+
+        if !entry.statics.is_empty() {
+            let mut output = File::create(dir.join(format!("static_{}.txt", machine)))?;
+            for (exe, entry) in &entry.statics {
+                let kind = if entry.pie { "static-pie" } else { "static" };
+                write!(output, "{} ({}, {} bytes", exe.display(), kind, entry.size)?;
+                if let Some(aliases) = report.aliases.get(exe) {
+                    write!(output, ", {} aliases", aliases.len())?;
+                }
+                writeln!(output, ")")?;
+            }
+        }
+    }
+
+    if !report.aliases.is_empty() {
+        let mut output = File::create(dir.join("aliases.txt"))?;
+        for (path, aliases) in &report.aliases {
+            writeln!(output, "{} ({} aliases)", path.display(), aliases.len())?;
+            for alias in aliases {
+                writeln!(output, "        = {}", alias.display())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Sonames, most used first, each followed by the executables using it
+fn write_consumers<'a>(
+    mut output: File,
+    consumers: impl Iterator<Item = (&'a str, Vec<&'a Path>)>,
+) -> std::io::Result<()> {
+    for (soname, exes) in consumers
+        .sorted_by_key(|(_, exes)| exes.len() as isize)
+        .rev()
+    {
+        writeln!(output, "{} ({} exes)", soname, exes.len())?;
+        for exe in exes.into_iter().sorted() {
+            writeln!(output, "        <= {}", exe.display())?;
+        }
+    }
+    Ok(())
+}
+
+/// Executables, each followed by the libraries it takes symbols from and the symbols
+fn write_imports(
+    mut output: File,
+    imports: &BTreeMap<PathBuf, BTreeMap<String, Vec<String>>>,
+) -> std::io::Result<()> {
+    for (exe, imports) in imports {
+        writeln!(output, "{}", exe.display())?;
+        for (soname, symbols) in imports {
+            writeln!(output, "    {} ({} symbols)", soname, symbols.len())?;
+            for symbol in symbols {
+                writeln!(output, "        {}", symbol)?;
+            }
+        }
+    }
+    Ok(())
+}
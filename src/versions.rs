@@ -0,0 +1,148 @@
+use crate::{dynamic_tag, vaddr_to_offset};
+use goblin::container::Ctx;
+use goblin::elf::dynamic::{
+    Dynamic, DT_VERDEF, DT_VERDEFNUM, DT_VERNEED, DT_VERNEEDNUM, DT_VERSYM,
+};
+use goblin::elf::program_header::ProgramHeader;
+use goblin::elf::section_header::{SectionHeader, SHT_GNU_VERDEF, SHT_GNU_VERNEED, SHT_GNU_VERSYM};
+use goblin::elf::symver::{
+    VerdefSection, VerneedSection, VersymSection, VERSYM_VERSION, VER_FLG_BASE, VER_FLG_WEAK,
+};
+use goblin::strtab::Strtab;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// A symbol version required from a library
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Need {
+    pub name: String,
+    /// ld.so only warns when the library does not define it
+    pub weak: bool,
+}
+
+/// Symbol versions of an object, from DT_VERNEED and DT_VERDEF
+#[derive(Debug, Default)]
+pub struct Versions {
+    /// Versions required from each DT_NEEDED entry
+    pub needs: BTreeMap<String, Vec<Need>>,
+    /// Versions defined, leaving out the base version naming the object
+    pub defs: Vec<String>,
+    /// Names of the version indices DT_VERSYM refers to, 0 and 1 being the local and global scopes
+    pub names: HashMap<u16, String>,
+}
+
+/// Describes a version table found through the dynamic segment the way goblin expects it, as a
+/// section header, since those are not read at all. Without `size`, the table runs to the end of
+/// the file, which is fine for those whose entries chain to each other.
+fn section(
+    dynamic: &Dynamic,
+    program_headers: &[ProgramHeader],
+    file_size: usize,
+    addr_tag: u64,
+    count: Option<u64>,
+    size: Option<u64>,
+    sh_type: u32,
+) -> Option<SectionHeader> {
+    let offset = vaddr_to_offset(program_headers, dynamic_tag(dynamic, addr_tag)?)?;
+    Some(SectionHeader {
+        sh_type,
+        sh_offset: offset,
+        sh_size: size.or_else(|| (file_size as u64).checked_sub(offset))?,
+        sh_info: count.unwrap_or(0) as u32,
+        ..Default::default()
+    })
+}
+
+/// Reads DT_VERNEED and DT_VERDEF. Malformed tables are treated as missing, ld.so does not need
+/// them to load anything.
+pub fn read(
+    bytes: &[u8],
+    program_headers: &[ProgramHeader],
+    dynamic: &Dynamic,
+    table: &Strtab,
+    ctx: Ctx,
+) -> Versions {
+    let mut versions = Versions::default();
+    let count = |tag| dynamic_tag(dynamic, tag);
+    let sections = [
+        (DT_VERNEED, DT_VERNEEDNUM, SHT_GNU_VERNEED),
+        (DT_VERDEF, DT_VERDEFNUM, SHT_GNU_VERDEF),
+    ]
+    .into_iter()
+    .filter_map(|(addr_tag, count_tag, sh_type)| {
+        let count = Some(count(count_tag)?);
+        section(
+            dynamic,
+            program_headers,
+            bytes.len(),
+            addr_tag,
+            count,
+            None,
+            sh_type,
+        )
+    })
+    .collect::<Vec<_>>();
+
+    if let Ok(Some(verneed)) = VerneedSection::parse(bytes, &sections, ctx) {
+        for need in verneed.iter() {
+            let file = match table.get_at(need.vn_file) {
+                Some(file) => file,
+                None => continue,
+            };
+            for aux in need.iter() {
+                let name = match table.get_at(aux.vna_name) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                versions
+                    .names
+                    .insert(aux.vna_other & VERSYM_VERSION, name.to_string());
+                versions
+                    .needs
+                    .entry(file.to_string())
+                    .or_default()
+                    .push(Need {
+                        name: name.to_string(),
+                        weak: aux.vna_flags & VER_FLG_WEAK != 0,
+                    });
+            }
+        }
+    }
+    if let Ok(Some(verdef)) = VerdefSection::parse(bytes, &sections, ctx) {
+        for def in verdef.iter().filter(|def| def.vd_flags & VER_FLG_BASE == 0) {
+            if let Some(name) = def.iter().next().and_then(|aux| table.get_at(aux.vda_name)) {
+                versions.names.insert(def.vd_ndx, name.to_string());
+                versions.defs.push(name.to_string());
+            }
+        }
+    }
+
+    for needs in versions.needs.values_mut() {
+        needs.sort();
+        needs.dedup();
+    }
+    versions.defs.sort();
+    versions.defs.dedup();
+    versions
+}
+
+/// The version index of each of the `count` dynamic symbols, from DT_VERSYM
+pub fn versym<'a>(
+    bytes: &'a [u8],
+    program_headers: &[ProgramHeader],
+    dynamic: &Dynamic,
+    count: usize,
+    ctx: Ctx,
+) -> Option<VersymSection<'a>> {
+    let size = (count as u64).checked_mul(2)?;
+    let section = section(
+        dynamic,
+        program_headers,
+        bytes.len(),
+        DT_VERSYM,
+        None,
+        Some(size),
+        SHT_GNU_VERSYM,
+    )?;
+    VersymSection::parse(bytes, &[section], ctx).ok()?
+}